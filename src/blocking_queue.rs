@@ -0,0 +1,239 @@
+use std::sync::{ Mutex, Condvar };
+use std::time::Duration;
+use crate::queue::Queue;
+
+struct State<T> {
+    queue: Queue<T>,
+    closed: bool,
+}
+
+// a FIFO queue of at most `capacity` values shared between threads: pushing
+// waits for room and popping waits for a value. Once closed, pushes are turned
+// away and pops drain what is left, then return None instead of waiting
+pub struct BlockingQueue<T> {
+    state: Mutex<State<T>>,
+    // signalled when a value arrives or the queue closes
+    not_empty: Condvar,
+    // signalled when a value leaves or the queue closes
+    not_full: Condvar,
+    capacity: usize,
+}
+
+impl<T> BlockingQueue<T> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "a blocking queue needs room for at least one value");
+        BlockingQueue {
+            state: Mutex::new(State {
+                queue: Queue::new(),
+                closed: false,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity,
+        }
+    }
+
+    // waits while the queue is full; hands `value` back if it is closed
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let state = self.state.lock().unwrap();
+        let mut state = self.not_full.wait_while(state, |state| state.queue.len() == self.capacity && !state.closed).unwrap();
+        if state.closed {
+            return Err(value);
+        }
+        state.queue.push(value);
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    // hands `value` back rather than waiting if the queue is full, or if it is closed
+    pub fn try_push(&self, value: T) -> Result<(), T> {
+        let mut state = self.state.lock().unwrap();
+        if state.queue.len() == self.capacity || state.closed {
+            return Err(value);
+        }
+        state.queue.push(value);
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    // waits while the queue is empty; None once it is closed and drained
+    pub fn pop(&self) -> Option<T> {
+        let state = self.state.lock().unwrap();
+        let mut state = self.not_empty.wait_while(state, |state| state.queue.is_empty() && !state.closed).unwrap();
+        let res = state.queue.pop();
+        if res.is_some() {
+            self.not_full.notify_one();
+        }
+        res
+    }
+
+    // None rather than waiting if the queue is empty
+    pub fn try_pop(&self) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        let res = state.queue.pop();
+        if res.is_some() {
+            self.not_full.notify_one();
+        }
+        res
+    }
+
+    // waits at most `timeout` for a value; None if none came, or the queue is closed and drained
+    #[allow(dead_code)]
+    pub fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        let state = self.state.lock().unwrap();
+        let (mut state, _) = self.not_empty
+            .wait_timeout_while(state, timeout, |state| state.queue.is_empty() && !state.closed)
+            .unwrap();
+        let res = state.queue.pop();
+        if res.is_some() {
+            self.not_full.notify_one();
+        }
+        res
+    }
+
+    // wakes every waiting thread; values already queued can still be popped
+    pub fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+
+    #[allow(dead_code)]
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().queue.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[allow(dead_code)]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Instant;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    // values only have to be Send for the queue to be shared
+    #[test]
+    fn shared_across_threads() {
+        assert_send_sync::<BlockingQueue<Cell<i32>>>();
+    }
+
+    #[test]
+    fn bounded_and_closable() {
+        let mailbox = BlockingQueue::new(2);
+        assert!(mailbox.try_push(1).is_ok());
+        assert!(mailbox.try_push(2).is_ok());
+        assert_eq!(mailbox.try_push(3), Err(3));
+        assert_eq!(mailbox.len(), 2);
+        assert_eq!(mailbox.capacity(), 2);
+        assert_eq!(mailbox.try_pop(), Some(1));
+        mailbox.close();
+        assert!(mailbox.is_closed());
+        assert_eq!(mailbox.push(4), Err(4));
+        assert_eq!(mailbox.pop(), Some(2));
+        assert_eq!(mailbox.pop(), None);
+        assert!(mailbox.is_empty());
+    }
+
+    // closing must release a pusher stuck on a full queue and a popper stuck on an empty one
+    #[test]
+    fn close_wakes_waiters() {
+        let full = Arc::new(BlockingQueue::new(1));
+        full.push(0).unwrap();
+        let empty: Arc<BlockingQueue<i32>> = Arc::new(BlockingQueue::new(1));
+        let pusher = {
+            let full = Arc::clone(&full);
+            thread::spawn(move || full.push(1))
+        };
+        let popper = {
+            let empty = Arc::clone(&empty);
+            thread::spawn(move || empty.pop())
+        };
+        thread::sleep(Duration::from_millis(50));
+        full.close();
+        empty.close();
+        assert_eq!(pusher.join().unwrap(), Err(1));
+        assert_eq!(popper.join().unwrap(), None);
+        assert_eq!(full.pop(), Some(0));
+    }
+
+    #[test]
+    fn pop_timeout_waits() {
+        let queue = Arc::new(BlockingQueue::new(1));
+        let start = Instant::now();
+        assert_eq!(queue.pop_timeout(Duration::from_millis(20)), None);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        let pusher = {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                queue.push(7).unwrap();
+            })
+        };
+        assert_eq!(queue.pop_timeout(Duration::from_secs(10)), Some(7));
+        pusher.join().unwrap();
+        // a closed, drained queue answers at once
+        queue.close();
+        let start = Instant::now();
+        assert_eq!(queue.pop_timeout(Duration::from_secs(10)), None);
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    // several producers and consumers hammer a small queue; every value must
+    // come out exactly once, and the consumers stop once it is closed and drained
+    #[test]
+    fn producers_and_consumers_race() {
+        let producers = 4;
+        let consumers = 3;
+        let per_producer = 20_000u64;
+        let jobs = Arc::new(BlockingQueue::new(8));
+        let consumer_handles: Vec<_> = (0..consumers).map(|_| {
+            let jobs = Arc::clone(&jobs);
+            thread::spawn(move || {
+                let mut count = 0;
+                let mut sum = 0;
+                while let Some(job) = jobs.pop() {
+                    count += 1;
+                    sum += job;
+                }
+                (count, sum)
+            })
+        }).collect();
+        let producer_handles: Vec<_> = (0..producers).map(|p| {
+            let jobs = Arc::clone(&jobs);
+            thread::spawn(move || {
+                for i in 0..per_producer {
+                    jobs.push(p * per_producer + i).unwrap();
+                }
+            })
+        }).collect();
+        for handle in producer_handles {
+            handle.join().unwrap();
+        }
+        jobs.close();
+        let (count, sum) = consumer_handles.into_iter()
+            .map(|handle| handle.join().unwrap())
+            .fold((0, 0), |(count, sum), (c, s)| (count + c, sum + s));
+        let total = producers * per_producer;
+        assert_eq!(count, total);
+        assert_eq!(sum, total * (total - 1) / 2);
+    }
+}
@@ -1,22 +1,24 @@
 use std::ptr::{ null_mut, drop_in_place };
 use std::alloc::{ alloc, dealloc, Layout, handle_alloc_error };
 use std::cmp::Ordering;
+use std::ops::{ Bound, RangeBounds };
+use std::marker::PhantomData;
+use std::collections::VecDeque;
+use crate::queue::Queue;
 use std::fmt;
 
-struct Node<T>
-where
-    T: Copy + Eq + Ord
-{
-    value: T,
-    parent: *mut Self,
-    left: *mut Self,
-    right: *mut Self,
+pub(crate) struct Node<T> {
+    pub(crate) value: T,
+    // later occurrences of values equal to `value`, oldest first; only a
+    // multiset ever puts anything here
+    pub(crate) repeats: VecDeque<T>,
+    pub(crate) size: usize,
+    pub(crate) parent: *mut Self,
+    pub(crate) left: *mut Self,
+    pub(crate) right: *mut Self,
 }
 
-impl<T> Node<T> 
-where
-    T: Copy + Ord + fmt::Display
-{
+impl<T> Node<T> {
     // fn new(value: T) -> Self {
     //     Node {
     //         value,
@@ -25,24 +27,82 @@ where
     //     }
     // }
 
-    fn new_ptr(value: T) -> *mut Self {
+    pub(crate) fn new_ptr(value: T) -> *mut Self {
         let layout = Layout::new::<Node<T>>();
         unsafe {
             let node_ptr = alloc(layout) as *mut Node<T>;
             if node_ptr.is_null() {
                 handle_alloc_error(layout);
             }
-            (*node_ptr).value = value;
-            (*node_ptr).parent = null_mut();
-            (*node_ptr).left = null_mut();
-            (*node_ptr).right = null_mut();
+            node_ptr.write(Node {
+                value,
+                repeats: VecDeque::new(),
+                size: 1,
+                parent: null_mut(),
+                left: null_mut(),
+                right: null_mut(),
+            });
             node_ptr
         }
     }
 
-    fn get_value(&self) -> T {
-        T::clone(&self.value)
-    }    
+    // drops the whole subtree under `node_ptr` and frees its memory
+    pub(crate) unsafe fn destroy(node_ptr: *mut Self) {
+        drop_in_place(node_ptr);
+        dealloc(node_ptr as *mut u8, Layout::new::<Node<T>>());
+    }
+
+    // frees a single detached node, handing its value back to the caller
+    pub(crate) unsafe fn into_value(node_ptr: *mut Self) -> T {
+        Self::into_occurrences(node_ptr).0
+    }
+
+    // like `into_value`, but hands back the repeats as well
+    unsafe fn into_occurrences(node_ptr: *mut Self) -> (T, VecDeque<T>) {
+        let value = std::ptr::read(&(*node_ptr).value);
+        let repeats = std::ptr::read(&(*node_ptr).repeats);
+        dealloc(node_ptr as *mut u8, Layout::new::<Node<T>>());
+        (value, repeats)
+    }
+
+    // occurrences held by this node, `value` included
+    pub(crate) fn count(&self) -> usize {
+        1 + self.repeats.len()
+    }
+
+    // the occurrence at `idx`, in the order they were inserted
+    fn occurrence(&self, idx: usize) -> &T {
+        if idx == 0 { &self.value } else { &self.repeats[idx - 1] }
+    }
+
+    fn occurrences(&self) -> impl Iterator<Item = &T> {
+        std::iter::once(&self.value).chain(self.repeats.iter())
+    }
+
+    // number of values in the subtree, repeats included, 0 for an empty one
+    pub(crate) fn size_of(node: *mut Self) -> usize {
+        if node.is_null() {
+            0
+        } else {
+            unsafe { (*node).size }
+        }
+    }
+
+    fn update_size(&mut self) {
+        self.size = self.count() + Self::size_of(self.left) + Self::size_of(self.right);
+    }
+
+    // recomputes subtree sizes from `from` up to and including `to` (or the root if `to` is null)
+    pub(crate) unsafe fn update_sizes(from: *mut Self, to: *mut Self) {
+        let mut node_ptr = from;
+        while !node_ptr.is_null() {
+            (*node_ptr).update_size();
+            if node_ptr == to {
+                break;
+            }
+            node_ptr = (*node_ptr).parent;
+        }
+    }
 
     fn set_left(&mut self, node: &mut Self) -> Result<(), String> {
         if !self.left.is_null() { 
@@ -64,13 +124,9 @@ where
         }
     }
 
-    fn is_leaf(&self) -> bool {
-        self.left.is_null() && self.right.is_null()
-    }
-
-    fn go_left_recursively(&self) -> &Self {
+    pub(crate) fn go_left_recursively(&self) -> &Self {
         if self.left.is_null() {
-            &self
+            self
         } else {
             unsafe {
                 (*self.left).go_left_recursively()
@@ -78,9 +134,9 @@ where
         }
     }
 
-    fn go_right_recursively(&self) -> &Self {
+    pub(crate) fn go_right_recursively(&self) -> &Self {
         if self.right.is_null() {
-            &self
+            self
         } else {
             unsafe {
                 (*self.right).go_right_recursively()
@@ -88,24 +144,46 @@ where
         }
     }
 
-    fn search(&self, value: T) -> Option<&Self> {
+    // `f` tells how a node's value relates to the one being looked for
+    pub(crate) fn search_by<F>(&self, f: F) -> Option<&Self>
+    where
+        F: Fn(&T) -> Ordering
+    {
+        unsafe {
+            match f(&self.value) {
+                Ordering::Less => if self.right.is_null() { None } else { (*self.right).search_by(f) },
+                Ordering::Equal => Some(self),
+                Ordering::Greater => if self.left.is_null() { None } else { (*self.left).search_by(f) },
+            }
+        }
+    }
+
+    pub(crate) fn search_by_mut<F>(&mut self, f: F) -> Option<&mut Self>
+    where
+        F: Fn(&T) -> Ordering
+    {
         unsafe {
-            match self.value.cmp(&value) {
-                Ordering::Less => if self.right.is_null() { None } else { (*self.right).search(value) },
+            match f(&self.value) {
+                Ordering::Less => if self.right.is_null() { None } else { (*self.right).search_by_mut(f) },
                 Ordering::Equal => Some(self),
-                Ordering::Greater => if self.left.is_null() { None } else { (*self.left).search(value) },
+                Ordering::Greater => if self.left.is_null() { None } else { (*self.left).search_by_mut(f) },
             }
         }
     }
 
-    fn add(&mut self, node: &mut Self) {
-        match self.value.cmp(&node.value) {
+    // `f` compares a node's value with the one being added
+    pub(crate) fn add_by<F>(&mut self, node: &mut Self, f: F)
+    where
+        F: Fn(&T, &T) -> Ordering
+    {
+        self.size += 1;
+        match f(&self.value, &node.value) {
             Ordering::Less | Ordering::Equal => {
                 if self.right.is_null() { 
                     self.set_right(node).unwrap(); 
                 } else {
                     unsafe {
-                        (*self.right).add(node);
+                        (*self.right).add_by(node, f);
                     }
                 }
             },
@@ -114,77 +192,273 @@ where
                     self.set_left(node).unwrap();
                 } else {
                     unsafe {
-                        (*self.left).add(node);
+                        (*self.left).add_by(node, f);
                     }
                 }
             },
         };
     }
 
-    fn del(&mut self, value: T) -> Result<(), String> {
-        unsafe { 
-            match self.value.cmp(&value) {
-                Ordering::Less => { 
-                    if self.right.is_null() { 
-                        Err("nothing to delete".to_string()) 
-                    } else { 
-                        (*self.right).del(value)
-                    }
-                },
-                Ordering::Greater =>  {
-                    if self.left.is_null() {
-                        Err("nothing to delete".to_string()) 
-                    } else { 
-                        (*self.left).del(value)
+    // unlinks the matching node and returns its value; a node without two
+    // children can only be deleted this way if it has a parent. Takes a raw
+    // pointer since the node may be freed, which no live reference may outlast
+    pub(crate) unsafe fn del_by<F>(node_ptr: *mut Self, f: F) -> Result<T, String>
+    where
+        F: Fn(&T) -> Ordering
+    {
+        match f(&(*node_ptr).value) {
+            Ordering::Less => {
+                if (*node_ptr).right.is_null() {
+                    Err("nothing to delete".to_string())
+                } else {
+                    let res = Self::del_by((*node_ptr).right, f);
+                    if res.is_ok() {
+                        (*node_ptr).update_size();
                     }
-                },
-                Ordering::Equal => {
-                    if self.is_leaf() {
-                        if self.value < (*self.parent).value {
-                            (*self.parent).left = null_mut();
-                        } else {
-                            (*self.parent).right = null_mut();
-                        }
-                        drop(self);
-                    } else if !self.left.is_null() && self.right.is_null() {
-                        (*self.left).parent = self.parent;
-                        if (*self.left).value < (*self.parent).value {
-                            (*self.parent).left = self.left;
-                        } else {
-                            (*self.parent).right = self.left;
-                        }
-                        drop(self);
-                    } else if self.left.is_null() && !self.right.is_null() {
-                        (*self.right).parent = self.parent;
-                        if (*self.right).value < (*self.parent).value {
-                            (*self.parent).left = self.right;
-                        } else {
-                            (*self.parent).right = self.right;
-                        }
-                        drop(self);
-                    } else {
-                        // if (*self.left).is_leaf() {
-                        //     self.value = (*self.left).value;
-                        //     (*self.left).del(self.value).unwrap();
-                        // } else {
-                            let node_ptr = (*self.right).go_left_recursively();
-                            self.value = (*node_ptr).value;
-                            (*self.right).del(self.value).unwrap();
-                        // }
+                    res
+                }
+            },
+            Ordering::Greater =>  {
+                if (*node_ptr).left.is_null() {
+                    Err("nothing to delete".to_string())
+                } else {
+                    let res = Self::del_by((*node_ptr).left, f);
+                    if res.is_ok() {
+                        (*node_ptr).update_size();
                     }
-                    Ok(())
-                },
+                    res
+                }
+            },
+            Ordering::Equal => {
+                if !(*node_ptr).left.is_null() && !(*node_ptr).right.is_null() {
+                    let next_ptr = (*(*node_ptr).right).go_left_recursively() as *const Self as *mut Self;
+                    std::ptr::swap(&mut (*node_ptr).value, &mut (*next_ptr).value);
+                    std::ptr::swap(&mut (*node_ptr).repeats, &mut (*next_ptr).repeats);
+                    let parent_ptr = (*next_ptr).parent;
+                    let value = Self::unlink(next_ptr);
+                    Self::update_sizes(parent_ptr, node_ptr);
+                    Ok(value)
+                } else {
+                    Ok(Self::unlink(node_ptr))
+                }
+            },
+        }
+    }
+
+    // splices the only child (if any) into the parent's slot and frees the node
+    unsafe fn unlink(node_ptr: *mut Self) -> T {
+        let parent_ptr = (*node_ptr).parent;
+        let child = if (*node_ptr).left.is_null() { (*node_ptr).right } else { (*node_ptr).left };
+        if !child.is_null() {
+            (*child).parent = parent_ptr;
+        }
+        if (*parent_ptr).left == node_ptr {
+            (*parent_ptr).left = child;
+        } else {
+            (*parent_ptr).right = child;
+        }
+        Self::into_value(node_ptr)
+    }
+
+    // cuts the subtree into values `f` finds Less and the rest along the search path;
+    // parent links of the two returned roots are left for the caller to set
+    pub(crate) unsafe fn split_by<F>(node_ptr: *mut Self, f: F) -> (*mut Self, *mut Self)
+    where
+        F: Fn(&T) -> Ordering
+    {
+        if node_ptr.is_null() {
+            return (null_mut(), null_mut());
+        }
+        let res = if f(&(*node_ptr).value) == Ordering::Less {
+            let (less, rest) = Self::split_by((*node_ptr).right, f);
+            (*node_ptr).right = less;
+            if !less.is_null() {
+                (*less).parent = node_ptr;
+            }
+            (node_ptr, rest)
+        } else {
+            let (less, rest) = Self::split_by((*node_ptr).left, f);
+            (*node_ptr).left = rest;
+            if !rest.is_null() {
+                (*rest).parent = node_ptr;
+            }
+            (less, node_ptr)
+        };
+        (*node_ptr).update_size();
+        res
+    }
+
+    // in-order successor, found through the right subtree or by climbing up
+    pub(crate) fn next_node(&self) -> *mut Self {
+        unsafe {
+            if !self.right.is_null() {
+                return (*self.right).go_left_recursively() as *const Self as *mut Self;
+            }
+            let mut node_ptr = self as *const Self as *mut Self;
+            let mut parent_ptr = self.parent;
+            while !parent_ptr.is_null() && (*parent_ptr).right == node_ptr {
+                node_ptr = parent_ptr;
+                parent_ptr = (*parent_ptr).parent;
             }
+            parent_ptr
         }
     }
 
-    fn infix_traverse(&self, vec: &mut Vec<T>) {
+    pub(crate) fn prev_node(&self) -> *mut Self {
+        unsafe {
+            if !self.left.is_null() {
+                return (*self.left).go_right_recursively() as *const Self as *mut Self;
+            }
+            let mut node_ptr = self as *const Self as *mut Self;
+            let mut parent_ptr = self.parent;
+            while !parent_ptr.is_null() && (*parent_ptr).left == node_ptr {
+                node_ptr = parent_ptr;
+                parent_ptr = (*parent_ptr).parent;
+            }
+            parent_ptr
+        }
+    }
+
+    // walks down remembering the last node whose value `fits`; when looking for
+    // the largest fitting value the walk continues right after a fit, else left
+    fn closest<F>(&self, fits: F, largest: bool) -> *mut Self
+    where
+        F: Fn(&T) -> bool
+    {
+        let mut res = null_mut();
+        let mut node_ptr = self as *const Self as *mut Self;
+        unsafe {
+            while !node_ptr.is_null() {
+                if fits(&(*node_ptr).value) {
+                    res = node_ptr;
+                    node_ptr = if largest { (*node_ptr).right } else { (*node_ptr).left };
+                } else {
+                    node_ptr = if largest { (*node_ptr).left } else { (*node_ptr).right };
+                }
+            }
+        }
+        res
+    }
+
+    // next node in prefix order: a child if there is one, otherwise the right
+    // sibling of the nearest ancestor reached from its left side
+    pub(crate) fn preorder_next(&self) -> *mut Self {
+        if !self.left.is_null() {
+            return self.left;
+        }
+        if !self.right.is_null() {
+            return self.right;
+        }
+        let mut node_ptr = self as *const Self as *mut Self;
+        unsafe {
+            let mut parent_ptr = self.parent;
+            while !parent_ptr.is_null() {
+                if (*parent_ptr).left == node_ptr && !(*parent_ptr).right.is_null() {
+                    return (*parent_ptr).right;
+                }
+                node_ptr = parent_ptr;
+                parent_ptr = (*parent_ptr).parent;
+            }
+        }
+        null_mut()
+    }
+
+    // first node in postfix order: the leaf reached by preferring left children
+    pub(crate) fn postorder_first(&self) -> *mut Self {
+        let mut node_ptr = self as *const Self as *mut Self;
+        unsafe {
+            loop {
+                if !(*node_ptr).left.is_null() {
+                    node_ptr = (*node_ptr).left;
+                } else if !(*node_ptr).right.is_null() {
+                    node_ptr = (*node_ptr).right;
+                } else {
+                    return node_ptr;
+                }
+            }
+        }
+    }
+
+    pub(crate) fn postorder_next(&self) -> *mut Self {
+        let parent_ptr = self.parent;
+        if parent_ptr.is_null() {
+            return null_mut();
+        }
+        unsafe {
+            if std::ptr::eq((*parent_ptr).left, self) && !(*parent_ptr).right.is_null() {
+                (*(*parent_ptr).right).postorder_first()
+            } else {
+                parent_ptr
+            }
+        }
+    }
+}
+
+impl<T> Node<T>
+where
+    T: Ord
+{
+    pub(crate) fn add(&mut self, node: &mut Self) {
+        self.add_by(node, T::cmp)
+    }
+}
+
+impl<T> Node<T> {
+    // deep copy of the subtree under `node_ptr`, hung below `parent`
+    fn clone_subtree(node_ptr: *mut Self, parent: *mut Self) -> *mut Self
+    where
+        T: Clone
+    {
+        if node_ptr.is_null() {
+            return null_mut();
+        }
+        unsafe {
+            let node = &*node_ptr;
+            let copy_ptr = Self::new_ptr(node.value.clone());
+            (*copy_ptr).repeats = node.repeats.clone();
+            (*copy_ptr).size = node.size;
+            (*copy_ptr).parent = parent;
+            (*copy_ptr).left = Self::clone_subtree(node.left, copy_ptr);
+            (*copy_ptr).right = Self::clone_subtree(node.right, copy_ptr);
+            copy_ptr
+        }
+    }
+
+    // perfectly balanced subtree over the next `len` sorted (value, repeats) pairs;
+    // the left half is built first so the pairs are consumed in order
+    fn build_sorted<I>(values: &mut I, len: usize) -> *mut Self
+    where
+        I: Iterator<Item = (T, VecDeque<T>)>
+    {
+        if len == 0 {
+            return null_mut();
+        }
+        let mid = len / 2;
+        let left = Self::build_sorted(values, mid);
+        let (value, repeats) = values.next().unwrap();
+        let node_ptr = Self::new_ptr(value);
+        unsafe {
+            (*node_ptr).repeats = repeats;
+            (*node_ptr).left = left;
+            (*node_ptr).right = Self::build_sorted(values, len - mid - 1);
+            if !(*node_ptr).left.is_null() {
+                (*(*node_ptr).left).parent = node_ptr;
+            }
+            if !(*node_ptr).right.is_null() {
+                (*(*node_ptr).right).parent = node_ptr;
+            }
+            (*node_ptr).update_size();
+        }
+        node_ptr
+    }
+
+    fn infix_traverse<'a>(&'a self, vec: &mut Vec<&'a T>) {
         if !self.left.is_null() {
             unsafe {
                 (*self.left).infix_traverse(vec);
             }
         }
-        vec.push(self.get_value());
+        vec.extend(self.occurrences());
         if !self.right.is_null() {
             unsafe {
                 (*self.right).infix_traverse(vec);
@@ -192,8 +466,8 @@ where
         }
     }
 
-    fn prefix_traverse(&self, vec: &mut Vec<T>) {
-        vec.push(self.get_value());
+    fn prefix_traverse<'a>(&'a self, vec: &mut Vec<&'a T>) {
+        vec.extend(self.occurrences());
         if !self.left.is_null() {
             unsafe {
                 (*self.left).prefix_traverse(vec);
@@ -206,7 +480,7 @@ where
         }
     }
 
-    fn postfix_traverse(&self, vec: &mut Vec<T>) {
+    fn postfix_traverse<'a>(&'a self, vec: &mut Vec<&'a T>) {
         if !self.left.is_null() {
             unsafe {
                 (*self.left).postfix_traverse(vec);
@@ -217,11 +491,25 @@ where
                 (*self.right).postfix_traverse(vec);
             }
         }
-        vec.push(self.get_value());
+        vec.extend(self.occurrences());
+    }
+}
+
+impl<T> Node<T>
+where
+    T: fmt::Display
+{
+    // the value, followed by its number of occurrences when there are several
+    fn label(&self) -> String {
+        if self.count() > 1 {
+            format!("{} x{}", self.value, self.count())
+        } else {
+            self.value.to_string()
+        }
     }
 
-    fn print_traverse(&self, string: &mut String, indent: usize) {
-        string.push_str(&format!("{}{}\n", " ".repeat(indent*2), self.value));
+    pub(crate) fn print_traverse(&self, string: &mut String, indent: usize) {
+        string.push_str(&format!("{}{}\n", " ".repeat(indent*2), self.label()));
         if !self.left.is_null() {
             unsafe {
                 (*self.left).print_traverse(string, indent+1);
@@ -233,75 +521,474 @@ where
             }
         }
     }
+
+    // writes this subtree as DOT statements and returns the id given to this node;
+    // missing children become point-shaped placeholders so left and right stay apart
+    fn dot_traverse(&self, string: &mut String, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        string.push_str(&format!("    n{} [label=\"{}\"];\n", id, self.label()));
+        for (child, side) in [(self.left, "L"), (self.right, "R")] {
+            if child.is_null() {
+                let null_id = *next_id;
+                *next_id += 1;
+                string.push_str(&format!("    n{} [shape=point];\n", null_id));
+                string.push_str(&format!("    n{} -> n{} [label=\"{}\"];\n", id, null_id, side));
+            } else {
+                let child_id = unsafe { (*child).dot_traverse(string, next_id) };
+                string.push_str(&format!("    n{} -> n{} [label=\"{}\"];\n", id, child_id, side));
+            }
+        }
+        id
+    }
+
+    // lays the subtree out as a block of equally wide lines; returns the lines,
+    // their width and the column the parent's connector should point at
+    fn ascii_block(&self) -> (Vec<String>, usize, usize) {
+        let label = self.label();
+        let len = label.chars().count();
+        let left = if self.left.is_null() { None } else { unsafe { Some((*self.left).ascii_block()) } };
+        let right = if self.right.is_null() { None } else { unsafe { Some((*self.right).ascii_block()) } };
+        match (left, right) {
+            (None, None) => (vec![label], len, len / 2),
+            (Some((lines, n, p)), None) => {
+                let mid = n + len / 2;
+                let mut res = vec![
+                    format!("{}{}", " ".repeat(n), label),
+                    format!("{}┌{}┘{}", " ".repeat(p), "─".repeat(mid - p - 1), " ".repeat(n + len - mid - 1)),
+                ];
+                res.extend(lines.iter().map(|line| format!("{}{}", line, " ".repeat(len))));
+                (res, n + len, mid)
+            },
+            (None, Some((lines, m, q))) => {
+                let mid = len / 2;
+                let mut res = vec![
+                    format!("{}{}", label, " ".repeat(m)),
+                    format!("{}└{}┐{}", " ".repeat(mid), "─".repeat(len + q - mid - 1), " ".repeat(m - q - 1)),
+                ];
+                res.extend(lines.iter().map(|line| format!("{}{}", " ".repeat(len), line)));
+                (res, len + m, mid)
+            },
+            (Some((left_lines, n, p)), Some((right_lines, m, q))) => {
+                let mid = n + len / 2;
+                let mut res = vec![
+                    format!("{}{}{}", " ".repeat(n), label, " ".repeat(m)),
+                    format!(
+                        "{}┌{}┴{}┐{}",
+                        " ".repeat(p),
+                        "─".repeat(mid - p - 1),
+                        "─".repeat(n + len + q - mid - 1),
+                        " ".repeat(m - q - 1),
+                    ),
+                ];
+                let blank_left = " ".repeat(n);
+                let blank_right = " ".repeat(m);
+                for i in 0..left_lines.len().max(right_lines.len()) {
+                    res.push(format!(
+                        "{}{}{}",
+                        left_lines.get(i).unwrap_or(&blank_left),
+                        " ".repeat(len),
+                        right_lines.get(i).unwrap_or(&blank_right),
+                    ));
+                }
+                (res, n + len + m, mid)
+            },
+        }
+    }
 }
 
-impl<T> Drop for Node<T>
-where
-    T: Copy + Ord
-{
+impl<T> Drop for Node<T> {
     fn drop(&mut self) {
         unsafe {
-            if !self.left.is_null() { drop_in_place(self.left); }
-            if !self.right.is_null() { drop_in_place(self.right); }
-            let layout = Layout::new::<Node<T>>();
-            dealloc((self as *mut Node<T>) as *mut u8, layout);
+            if !self.left.is_null() { Node::destroy(self.left); }
+            if !self.right.is_null() { Node::destroy(self.right); }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeViolation<T> {
+    // `node` lies on the wrong side of its ancestor `bound`
+    Order { node: T, bound: T },
+    // the parent pointer of `node` does not lead to the node linking to it
+    ParentLink { node: T },
+    // the element counter disagrees with the number of reachable nodes
+    Count { recorded: usize, actual: usize },
+    Size { node: T, recorded: usize, actual: usize },
+    // `node` holds several occurrences outside multiset mode
+    Multiplicity { node: T, count: usize },
+    // a multiset keeps `node` in more than one node
+    Duplicate { node: T },
+    Height { node: T, recorded: usize, actual: usize },
+    Balance { node: T, balance: isize },
+    RedRoot { node: T },
+    // red `node` hangs under a red parent
+    RedChild { node: T },
+    // paths through `node` reach leaves over different numbers of black nodes
+    BlackHeight { node: T },
+}
+
+impl<T> fmt::Display for TreeViolation<T>
+where
+    T: fmt::Display
+{
+    fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreeViolation::Order { node, bound } => write!(f, "{} is on the wrong side of {}", node, bound),
+            TreeViolation::ParentLink { node } => write!(f, "{} has a broken parent link", node),
+            TreeViolation::Count { recorded, actual } => write!(f, "tree records {} elements but holds {}", recorded, actual),
+            TreeViolation::Size { node, recorded, actual } => write!(f, "{} records subtree size {} but has {}", node, recorded, actual),
+            TreeViolation::Multiplicity { node, count } => write!(f, "{} records {} occurrences", node, count),
+            TreeViolation::Duplicate { node } => write!(f, "{} is stored in several nodes", node),
+            TreeViolation::Height { node, recorded, actual } => write!(f, "{} records height {} but has {}", node, recorded, actual),
+            TreeViolation::Balance { node, balance } => write!(f, "{} has balance factor {}", node, balance),
+            TreeViolation::RedRoot { node } => write!(f, "root {} is red", node),
+            TreeViolation::RedChild { node } => write!(f, "red {} has a red parent", node),
+            TreeViolation::BlackHeight { node } => write!(f, "black heights differ under {}", node),
         }
     }
 }
 
-pub struct BinarySearchTree<T>
+// what `BinarySearchTree::diff` found, each list in sorted order; repeated
+// values are matched occurrence by occurrence
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeDiff<'a, T> {
+    pub only_in_self: Vec<&'a T>,
+    pub only_in_other: Vec<&'a T>,
+    pub in_both: Vec<&'a T>,
+}
+
+// orders the values of a tree in place of `Ord`; closures taking two
+// references and returning an `Ordering` work as comparators too
+pub trait Comparator<T> {
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+}
+
+// the `Ord` order, used by `BinarySearchTree::new`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NaturalOrder;
+
+impl<T> Comparator<T> for NaturalOrder
+where
+    T: Ord
+{
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+impl<T, F> Comparator<T> for F
 where
-    T: Copy + Ord
+    F: Fn(&T, &T) -> Ordering
+{
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self(a, b)
+    }
+}
+
+pub struct BinarySearchTree<T, C = NaturalOrder>
+where
+    C: Comparator<T>
 {
     root: *mut Node<T>,
     num_elements: usize,
+    // set in multiset mode, where a value equal to one already stored joins that
+    // node's repeats instead of getting a node of its own
+    multiset: bool,
+    comparator: C,
 }
 
 impl<T> BinarySearchTree<T>
 where
-    T: Copy + Ord + fmt::Display
+    T: Ord
 {
     pub fn new() -> Self {
+        Self::with_comparator(NaturalOrder)
+    }
+
+    pub fn new_multiset() -> Self {
+        Self::multiset_with_comparator(NaturalOrder)
+    }
+
+    // builds a perfectly balanced tree in O(n) out of clones of `values`, which
+    // must be in ascending order; equal values each get a node of their own
+    pub fn from_sorted(values: &[T]) -> Result<Self, String>
+    where
+        T: Clone
+    {
+        Self::from_sorted_with_comparator(values, NaturalOrder)
+    }
+
+    // like `from_sorted`, but a multiset, so each run of equal values shares a node
+    pub fn multiset_from_sorted(values: &[T]) -> Result<Self, String>
+    where
+        T: Clone
+    {
+        Self::multiset_from_sorted_with_comparator(values, NaturalOrder)
+    }
+}
+
+impl<T, C> BinarySearchTree<T, C>
+where
+    C: Comparator<T>
+{
+    pub fn with_comparator(comparator: C) -> Self {
         BinarySearchTree {
             root: null_mut(),
             num_elements: 0,
+            multiset: false,
+            comparator,
+        }
+    }
+
+    pub fn multiset_with_comparator(comparator: C) -> Self {
+        BinarySearchTree {
+            root: null_mut(),
+            num_elements: 0,
+            multiset: true,
+            comparator,
+        }
+    }
+
+    // `values` must be ascending by `comparator`; as with `insert`, equal values
+    // end up in separate nodes
+    pub fn from_sorted_with_comparator(values: &[T], comparator: C) -> Result<Self, String>
+    where
+        T: Clone
+    {
+        Self::build_from_sorted(values, comparator, false)
+    }
+
+    pub fn multiset_from_sorted_with_comparator(values: &[T], comparator: C) -> Result<Self, String>
+    where
+        T: Clone
+    {
+        Self::build_from_sorted(values, comparator, true)
+    }
+
+    // a multiset folds each run of equal values into the node of its first one
+    fn build_from_sorted(values: &[T], comparator: C, multiset: bool) -> Result<Self, String>
+    where
+        T: Clone
+    {
+        if values.windows(2).any(|pair| comparator.compare(&pair[0], &pair[1]) == Ordering::Greater) {
+            return Err("values are not in ascending order".to_string());
+        }
+        let mut occurrences: Vec<(T, VecDeque<T>)> = Vec::new();
+        for value in values {
+            match occurrences.last_mut() {
+                Some((first, repeats)) if multiset && comparator.compare(first, value) == Ordering::Equal => {
+                    repeats.push_back(value.clone());
+                },
+                _ => occurrences.push((value.clone(), VecDeque::new())),
+            }
         }
+        let len = occurrences.len();
+        Ok(BinarySearchTree {
+            root: Node::build_sorted(&mut occurrences.into_iter(), len),
+            num_elements: values.len(),
+            multiset,
+            comparator,
+        })
+    }
+
+    pub fn is_multiset(&self) -> bool {
+        self.multiset
+    }
+
+    pub fn comparator(&self) -> &C {
+        &self.comparator
+    }
+
+    // keeps values < `value` in this tree and returns the rest, in O(height)
+    pub fn split_off(&mut self, value: &T) -> Self
+    where
+        C: Clone
+    {
+        let (less, rest) = unsafe { Node::split_by(self.root, |v| self.comparator.compare(v, value)) };
+        for root in [less, rest] {
+            if !root.is_null() {
+                unsafe { (*root).parent = null_mut(); }
+            }
+        }
+        self.root = less;
+        self.num_elements = Node::size_of(less);
+        BinarySearchTree {
+            root: rest,
+            num_elements: Node::size_of(rest),
+            multiset: self.multiset,
+            comparator: self.comparator.clone(),
+        }
+    }
+
+    // moves every value of `other` into this tree, rebuilding it balanced in O(n + m)
+    pub fn append(&mut self, other: &mut Self) {
+        if other.num_elements == 0 {
+            return;
+        }
+        let num_elements = self.num_elements + other.num_elements;
+        let mut merged: Vec<(T, VecDeque<T>)> = Vec::with_capacity(num_elements);
+        let mut left = self.take_occurrences().into_iter().peekable();
+        let mut right = other.take_occurrences().into_iter().peekable();
+        loop {
+            let next = match (left.peek(), right.peek()) {
+                (Some(a), Some(b)) => {
+                    if self.comparator.compare(&a.0, &b.0) != Ordering::Greater { left.next() } else { right.next() }
+                },
+                (Some(_), None) => left.next(),
+                (None, Some(_)) => right.next(),
+                (None, None) => break,
+            };
+            let (value, repeats) = next.unwrap();
+            match merged.last_mut() {
+                Some(last) if self.is_multiset() && self.comparator.compare(&last.0, &value) == Ordering::Equal => {
+                    last.1.push_back(value);
+                    last.1.extend(repeats);
+                },
+                _ if self.is_multiset() => merged.push((value, repeats)),
+                _ => {
+                    // repeats from a multiset `other` get nodes of their own here
+                    merged.push((value, VecDeque::new()));
+                    merged.extend(repeats.into_iter().map(|repeat| (repeat, VecDeque::new())));
+                },
+            }
+        }
+        let len = merged.len();
+        self.root = Node::build_sorted(&mut merged.into_iter(), len);
+        self.num_elements = num_elements;
+    }
+
+    pub fn clear(&mut self) {
+        if !self.root.is_null() {
+            unsafe { Node::destroy(self.root); }
+        }
+        self.root = null_mut();
+        self.num_elements = 0;
+    }
+
+    // empties the tree, moving every node's value out with its repeats, in order
+    fn take_occurrences(&mut self) -> Vec<(T, VecDeque<T>)> {
+        let mut nodes = Vec::new();
+        let mut node_ptr = if self.root.is_null() {
+            null_mut()
+        } else {
+            unsafe { (*self.root).go_left_recursively() as *const Node<T> as *mut Node<T> }
+        };
+        while !node_ptr.is_null() {
+            nodes.push(node_ptr);
+            node_ptr = unsafe { (*node_ptr).next_node() };
+        }
+        self.root = null_mut();
+        self.num_elements = 0;
+        // the links are no longer followed, so each node can be freed on its own
+        nodes.into_iter().map(|node_ptr| unsafe { Node::into_occurrences(node_ptr) }).collect()
+    }
+
+    // concatenates two trees; when every value of `self` is <= every value of
+    // `other` this only costs O(height), otherwise it falls back to `append`
+    pub fn join(mut self, mut other: Self) -> Self {
+        if self.num_elements == 0 {
+            return other;
+        }
+        // in a multiset equal values have to end up in the same node
+        let order = match (self.max(), other.min()) {
+            (Some(max), Some(min)) => self.comparator.compare(max, min),
+            _ => Ordering::Less,
+        };
+        if other.num_elements == 0 || order == Ordering::Greater ||
+            self.is_multiset() != other.is_multiset() || (self.is_multiset() && order == Ordering::Equal)
+        {
+            self.append(&mut other);
+            return self;
+        }
+        // the smallest node of `other` becomes the new root, with all of its occurrences
+        let repeats = unsafe {
+            let min_ptr = (*other.root).go_left_recursively() as *const Node<T> as *mut Node<T>;
+            let repeats = std::mem::take(&mut (*min_ptr).repeats);
+            Node::update_sizes(min_ptr, null_mut());
+            other.num_elements -= repeats.len();
+            repeats
+        };
+        let root_ptr = Node::new_ptr(other.take_min().unwrap());
+        unsafe {
+            (*root_ptr).repeats = repeats;
+            (*root_ptr).left = self.root;
+            (*root_ptr).right = other.root;
+            for child in [self.root, other.root] {
+                if !child.is_null() {
+                    (*child).parent = root_ptr;
+                }
+            }
+            (*root_ptr).update_size();
+        }
+        self.root = root_ptr;
+        self.num_elements = Node::size_of(root_ptr);
+        other.root = null_mut();
+        other.num_elements = 0;
+        self
     }
 
     pub fn insert(&mut self, value: T) {
+        if self.is_multiset() {
+            let node_ptr = self.find(&value);
+            if !node_ptr.is_null() {
+                unsafe {
+                    (*node_ptr).repeats.push_back(value);
+                    Node::update_sizes(node_ptr, null_mut());
+                }
+                self.num_elements += 1;
+                return;
+            }
+        }
         let new_ptr = Node::new_ptr(value);
         if self.root.is_null() {
             self.root = new_ptr;
         } else {
             unsafe {
-                (*self.root).add(&mut *new_ptr);
+                (*self.root).add_by(&mut *new_ptr, |a, b| self.comparator.compare(a, b));
             }
         }
         self.num_elements += 1;
     }
 
-    pub fn contents(&self, value: T) -> bool {
+    pub fn contents(&self, value: &T) -> bool {
         if self.root.is_null() {
             assert_eq!(self.num_elements, 0);
             false
         } else {
             unsafe {
-                (*self.root).search(value).is_some()
+                (*self.root).search_by(|v| self.comparator.compare(v, value)).is_some()
             } 
         }
     }
 
-    pub fn remove(&mut self, value: T) -> Result<(), String> {
+    // takes out a single occurrence of `value`; a multiset gives back the one inserted last
+    pub fn remove(&mut self, value: &T) -> Result<T, String> {
+        if self.is_multiset() {
+            let node_ptr = self.find(value);
+            if !node_ptr.is_null() {
+                unsafe {
+                    if let Some(res) = (*node_ptr).repeats.pop_back() {
+                        Node::update_sizes(node_ptr, null_mut());
+                        self.num_elements -= 1;
+                        return Ok(res);
+                    }
+                }
+            }
+        }
         unsafe {
             if self.root.is_null() {
                 assert_eq!(self.num_elements, 0);
                 Err("tree is already empty".to_string())
             } else if self.num_elements == 1 {
-                drop_in_place(self.root);
+                if self.comparator.compare(&(*self.root).value, value) != Ordering::Equal {
+                    return Err("nothing to delete".to_string());
+                }
+                let res = Node::into_value(self.root);
                 self.root = null_mut();
                 self.num_elements -= 1;
-                Ok(())
-            } else if value == (*self.root).value &&
+                Ok(res)
+            } else if self.comparator.compare(&(*self.root).value, value) == Ordering::Equal &&
                 !(!(*self.root).left.is_null() && !(*self.root).right.is_null())
             {
                 let tmp = self.root;
@@ -310,44 +997,344 @@ where
                 } else if (*self.root).right.is_null() {
                     self.root = (*self.root).left;
                 }
-                (*tmp).left = null_mut();
-                (*tmp).right = null_mut();
-                drop_in_place(tmp);
+                if !self.root.is_null() {
+                    (*self.root).parent = null_mut();
+                }
                 self.num_elements -= 1;
-                Ok(())
+                Ok(Node::into_value(tmp))
             } else {
+                let res = Node::del_by(self.root, |v| self.comparator.compare(v, value))?;
                 self.num_elements -= 1;
-                (*self.root).del(value)
+                Ok(res)
             }
         }
     }
 
-    pub fn min(&self) -> Option<T> {
-        if self.root.is_null() {
-            None
-        } else {
-            unsafe {
-                Some( (*self.root).go_left_recursively().get_value() )
-            }
-        }
+    pub fn remove_one(&mut self, value: &T) -> Result<T, String> {
+        self.remove(value)
     }
-    
-    pub fn max(&self) -> Option<T> {
-        if self.root.is_null() {
-            assert_eq!(self.num_elements, 0);
-            None
-        } else {
-            unsafe {
-                Some( (*self.root).go_right_recursively().get_value() )
+
+    // drops every occurrence of `value`, returning how many there were
+    pub fn remove_all(&mut self, value: &T) -> usize {
+        if !self.is_multiset() {
+            let mut res = 0;
+            while self.remove(value).is_ok() {
+                res += 1;
             }
+            return res;
+        }
+        let node_ptr = self.find(value);
+        if node_ptr.is_null() {
+            return 0;
+        }
+        unsafe {
+            let res = (*node_ptr).count();
+            (*node_ptr).repeats.clear();
+            Node::update_sizes(node_ptr, null_mut());
+            self.num_elements -= res - 1;
+            self.remove(value).unwrap();
+            res
         }
     }
 
-    pub fn count(&self) -> usize {
+    // occurrences of `value`, whether they share a node or not
+    pub fn count_of(&self, value: &T) -> usize {
+        self.count_below(|v| self.comparator.compare(v, value) != Ordering::Greater) - self.rank(value)
+    }
+
+    fn find(&self, value: &T) -> *mut Node<T> {
+        if self.root.is_null() {
+            return null_mut();
+        }
+        unsafe {
+            match (*self.root).search_by(|v| self.comparator.compare(v, value)) {
+                Some(node) => node as *const Node<T> as *mut Node<T>,
+                None => null_mut(),
+            }
+        }
+    }
+
+    pub fn min(&self) -> Option<&T> {
+        if self.root.is_null() {
+            None
+        } else {
+            unsafe {
+                Some( &(*self.root).go_left_recursively().value )
+            }
+        }
+    }
+    
+    pub fn max(&self) -> Option<&T> {
+        if self.root.is_null() {
+            assert_eq!(self.num_elements, 0);
+            None
+        } else {
+            unsafe {
+                Some( &(*self.root).go_right_recursively().value )
+            }
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        debug_assert_eq!(self.num_elements, Node::size_of(self.root));
         self.num_elements
     }
 
-    pub fn infix_traverse(&self) -> Vec<T> {
+    // checks ordering, parent links, subtree sizes, occurrence counts and the element counter
+    pub fn validate(&self) -> Result<(), TreeViolation<&T>> {
+        let mut actual = 0;
+        unsafe {
+            if !self.root.is_null() && !(*self.root).parent.is_null() {
+                return Err(TreeViolation::ParentLink { node: &(*self.root).value });
+            }
+            // every node is visited along with the closest ancestors bounding it from below and above
+            let mut stack = vec![(self.root, null_mut::<Node<T>>(), null_mut::<Node<T>>())];
+            let order = |a: &T, bound: *mut Node<T>| self.comparator.compare(a, &(*bound).value);
+            while let Some((node_ptr, low, high)) = stack.pop() {
+                if node_ptr.is_null() {
+                    continue;
+                }
+                let node = &*node_ptr;
+                actual += node.count();
+                if !low.is_null() && order(&node.value, low) == Ordering::Less {
+                    return Err(TreeViolation::Order { node: &node.value, bound: &(*low).value });
+                }
+                if !high.is_null() && order(&node.value, high) == Ordering::Greater {
+                    return Err(TreeViolation::Order { node: &node.value, bound: &(*high).value });
+                }
+                if !self.is_multiset() && node.count() > 1 {
+                    return Err(TreeViolation::Multiplicity { node: &node.value, count: node.count() });
+                }
+                let equal_bound = |bound: *mut Node<T>| !bound.is_null() && order(&node.value, bound) == Ordering::Equal;
+                if self.is_multiset() && (equal_bound(low) || equal_bound(high)) {
+                    return Err(TreeViolation::Duplicate { node: &node.value });
+                }
+                for child in [node.left, node.right] {
+                    if !child.is_null() && (*child).parent != node_ptr {
+                        return Err(TreeViolation::ParentLink { node: &(*child).value });
+                    }
+                }
+                let size = node.count() + Node::size_of(node.left) + Node::size_of(node.right);
+                if node.size != size {
+                    return Err(TreeViolation::Size { node: &node.value, recorded: node.size, actual: size });
+                }
+                stack.push((node.left, low, node_ptr));
+                stack.push((node.right, node_ptr, high));
+            }
+        }
+        if actual != self.num_elements {
+            return Err(TreeViolation::Count { recorded: self.num_elements, actual });
+        }
+        Ok(())
+    }
+
+    // number of values strictly less than `value`
+    pub fn rank(&self, value: &T) -> usize {
+        self.count_below(|v| self.comparator.compare(v, value) == Ordering::Less)
+    }
+
+    // number of values in the prefix of the order for which `below` holds
+    fn count_below<F>(&self, below: F) -> usize
+    where
+        F: Fn(&T) -> bool
+    {
+        let mut res = 0;
+        let mut node_ptr = self.root;
+        unsafe {
+            while !node_ptr.is_null() {
+                if below(&(*node_ptr).value) {
+                    res += Node::size_of((*node_ptr).left) + (*node_ptr).count();
+                    node_ptr = (*node_ptr).right;
+                } else {
+                    node_ptr = (*node_ptr).left;
+                }
+            }
+        }
+        res
+    }
+
+    // k-th smallest value, counting from 0
+    pub fn select(&self, k: usize) -> Option<&T> {
+        if k >= self.num_elements {
+            return None;
+        }
+        let mut k = k;
+        let mut node_ptr = self.root;
+        unsafe {
+            loop {
+                let node = &*node_ptr;
+                let left_size = Node::size_of(node.left);
+                if k < left_size {
+                    node_ptr = node.left;
+                } else if k < left_size + node.count() {
+                    return Some(node.occurrence(k - left_size));
+                } else {
+                    k -= left_size + node.count();
+                    node_ptr = node.right;
+                }
+            }
+        }
+    }
+
+    // lower median for an even number of values
+    pub fn median(&self) -> Option<&T> {
+        if self.num_elements == 0 {
+            None
+        } else {
+            self.select((self.num_elements - 1) / 2)
+        }
+    }
+
+    // largest value <= `value`
+    pub fn floor(&self, value: &T) -> Option<&T> {
+        self.closest(|v| self.comparator.compare(v, value) != Ordering::Greater, true)
+    }
+
+    // smallest value >= `value`
+    pub fn ceiling(&self, value: &T) -> Option<&T> {
+        self.closest(|v| self.comparator.compare(v, value) != Ordering::Less, false)
+    }
+
+    // largest value < `value`
+    pub fn predecessor(&self, value: &T) -> Option<&T> {
+        self.closest(|v| self.comparator.compare(v, value) == Ordering::Less, true)
+    }
+
+    // smallest value > `value`
+    pub fn successor(&self, value: &T) -> Option<&T> {
+        self.closest(|v| self.comparator.compare(v, value) == Ordering::Greater, false)
+    }
+
+    // both ends are looked up front, so the bounds themselves are not kept around
+    pub fn range<R>(&self, range: R) -> Range<'_, T>
+    where
+        R: RangeBounds<T>
+    {
+        if self.root.is_null() {
+            return Range { node: null_mut(), last: null_mut(), taken: 0, tree: PhantomData };
+        }
+        let (node, last) = unsafe {
+            let node = match range.start_bound() {
+                Bound::Included(start) => {
+                    (*self.root).closest(|v| self.comparator.compare(v, start) != Ordering::Less, false)
+                },
+                Bound::Excluded(start) => {
+                    (*self.root).closest(|v| self.comparator.compare(v, start) == Ordering::Greater, false)
+                },
+                Bound::Unbounded => (*self.root).go_left_recursively() as *const Node<T> as *mut Node<T>,
+            };
+            let last = match range.end_bound() {
+                Bound::Included(end) => {
+                    (*self.root).closest(|v| self.comparator.compare(v, end) != Ordering::Greater, true)
+                },
+                Bound::Excluded(end) => {
+                    (*self.root).closest(|v| self.comparator.compare(v, end) == Ordering::Less, true)
+                },
+                Bound::Unbounded => (*self.root).go_right_recursively() as *const Node<T> as *mut Node<T>,
+            };
+            if node.is_null() || last.is_null() ||
+                self.comparator.compare(&(*node).value, &(*last).value) == Ordering::Greater
+            {
+                (null_mut(), null_mut())
+            } else {
+                (node, last)
+            }
+        };
+        Range {
+            node,
+            last,
+            taken: 0,
+            tree: PhantomData,
+        }
+    }
+
+    fn closest<F>(&self, fits: F, largest: bool) -> Option<&T>
+    where
+        F: Fn(&T) -> bool
+    {
+        if self.root.is_null() {
+            return None;
+        }
+        unsafe {
+            let node_ptr = (*self.root).closest(fits, largest);
+            if node_ptr.is_null() { None } else { Some(&(*node_ptr).value) }
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        let (front, back) = if self.root.is_null() {
+            (null_mut(), null_mut())
+        } else {
+            unsafe {
+                (
+                    (*self.root).go_left_recursively() as *const Node<T> as *mut Node<T>,
+                    (*self.root).go_right_recursively() as *const Node<T> as *mut Node<T>,
+                )
+            }
+        };
+        Iter {
+            front,
+            back,
+            front_taken: 0,
+            back_taken: 0,
+            len: self.num_elements,
+            tree: PhantomData,
+        }
+    }
+
+    pub fn iter_preorder(&self) -> PreorderIter<'_, T> {
+        PreorderIter {
+            node: self.root,
+            taken: 0,
+            tree: PhantomData,
+        }
+    }
+
+    pub fn iter_postorder(&self) -> PostorderIter<'_, T> {
+        let node = if self.root.is_null() {
+            null_mut()
+        } else {
+            unsafe { (*self.root).postorder_first() }
+        };
+        PostorderIter {
+            node,
+            taken: 0,
+            tree: PhantomData,
+        }
+    }
+
+    pub fn iter_level_order(&self) -> LevelOrderIter<'_, T> {
+        let mut queue = Queue::new();
+        if !self.root.is_null() {
+            queue.push(self.root);
+        }
+        LevelOrderIter {
+            queue,
+            node: null_mut(),
+            taken: 0,
+            tree: PhantomData,
+        }
+    }
+
+    // in-order values with each repeated one listed once; every other traversal
+    // and iterator lists all occurrences
+    pub fn infix_traverse_distinct(&self) -> Vec<&T> {
+        let mut res = Vec::new();
+        let mut node_ptr = if self.root.is_null() {
+            null_mut()
+        } else {
+            unsafe { (*self.root).go_left_recursively() as *const Node<T> as *mut Node<T> }
+        };
+        while !node_ptr.is_null() {
+            unsafe {
+                res.push(&(*node_ptr).value);
+                node_ptr = (*node_ptr).next_node();
+            }
+        }
+        res
+    }
+
+    pub fn infix_traverse(&self) -> Vec<&T> {
         let mut res = Vec::with_capacity(self.num_elements);
         if !self.root.is_null() {
             unsafe {
@@ -357,7 +1344,7 @@ where
         res
     }
 
-    pub fn prefix_traverse(&self) -> Vec<T> {
+    pub fn prefix_traverse(&self) -> Vec<&T> {
         let mut res = Vec::with_capacity(self.num_elements);
         if !self.root.is_null() {
             unsafe {
@@ -367,7 +1354,7 @@ where
         res
     }
 
-    pub fn postfix_traverse(&self) -> Vec<T> {
+    pub fn postfix_traverse(&self) -> Vec<&T> {
         let mut res = Vec::with_capacity(self.num_elements);
         if !self.root.is_null() {
             unsafe {
@@ -376,22 +1363,546 @@ where
         }
         res
     }
+
+    // Graphviz digraph with explicitly labeled left/right edges
+    pub fn to_dot(&self) -> String
+    where
+        T: fmt::Display
+    {
+        let mut out = String::from("digraph BinarySearchTree {\n");
+        if !self.root.is_null() {
+            let mut next_id = 0;
+            unsafe {
+                (*self.root).dot_traverse(&mut out, &mut next_id);
+            }
+        }
+        out.push('}');
+        out
+    }
+
+    // top-down drawing with box-drawing connectors under every parent
+    pub fn to_ascii_tree(&self) -> String
+    where
+        T: fmt::Display
+    {
+        if self.root.is_null() {
+            return "empty tree".to_string();
+        }
+        let (lines, _, _) = unsafe { (*self.root).ascii_block() };
+        lines.iter().map(|line| line.trim_end()).collect::<Vec<_>>().join("\n")
+    }
+
+    pub fn level_order_traverse(&self) -> Vec<&T> {
+        self.levels().into_iter().flatten().collect()
+    }
+
+    // values grouped by depth, the root alone on level 0
+    pub fn levels(&self) -> Vec<Vec<&T>> {
+        let mut res = Vec::new();
+        let mut queue = Queue::new();
+        if !self.root.is_null() {
+            queue.push(self.root);
+        }
+        while !queue.is_empty() {
+            let mut level = Vec::with_capacity(queue.len());
+            for _ in 0..queue.len() {
+                let node_ptr = queue.pop().unwrap();
+                unsafe {
+                    level.extend((*node_ptr).occurrences());
+                    if !(*node_ptr).left.is_null() {
+                        queue.push((*node_ptr).left);
+                    }
+                    if !(*node_ptr).right.is_null() {
+                        queue.push((*node_ptr).right);
+                    }
+                }
+            }
+            res.push(level);
+        }
+        res
+    }
+
+    // number of levels: 0 for an empty tree, 1 for a lone root; the stack of
+    // (node, depth) pairs grows with the height rather than the widest level
+    pub fn height(&self) -> usize {
+        let mut res = 0;
+        let mut stack = Vec::new();
+        if !self.root.is_null() {
+            stack.push((self.root, 1));
+        }
+        while let Some((node_ptr, depth)) = stack.pop() {
+            res = res.max(depth);
+            let node = unsafe { &*node_ptr };
+            for child in [node.left, node.right] {
+                if !child.is_null() {
+                    stack.push((child, depth + 1));
+                }
+            }
+        }
+        res
+    }
+
+    // whether both trees hold equal values in the same places, which `==` does not ask for;
+    // values are compared with this tree's comparator
+    pub fn same_shape(&self, other: &Self) -> bool {
+        let mut stack = vec![(self.root, other.root)];
+        unsafe {
+            while let Some((a, b)) = stack.pop() {
+                if a.is_null() || b.is_null() {
+                    if a != b {
+                        return false;
+                    }
+                    continue;
+                }
+                if self.comparator.compare(&(*a).value, &(*b).value) != Ordering::Equal || (*a).count() != (*b).count() {
+                    return false;
+                }
+                stack.push(((*a).left, (*b).left));
+                stack.push(((*a).right, (*b).right));
+            }
+        }
+        true
+    }
+
+    // splits the values of both trees into those only one of them holds and those they share
+    pub fn diff<'a>(&'a self, other: &'a Self) -> TreeDiff<'a, T> {
+        let mut res = TreeDiff {
+            only_in_self: Vec::new(),
+            only_in_other: Vec::new(),
+            in_both: Vec::new(),
+        };
+        let mut left = self.iter().peekable();
+        let mut right = other.iter().peekable();
+        loop {
+            match (left.peek(), right.peek()) {
+                (Some(a), Some(b)) => match self.comparator.compare(a, b) {
+                    Ordering::Less => res.only_in_self.push(left.next().unwrap()),
+                    Ordering::Equal => {
+                        res.in_both.push(left.next().unwrap());
+                        right.next();
+                    },
+                    Ordering::Greater => res.only_in_other.push(right.next().unwrap()),
+                },
+                (Some(_), None) => res.only_in_self.push(left.next().unwrap()),
+                (None, Some(_)) => res.only_in_other.push(right.next().unwrap()),
+                (None, None) => break,
+            }
+        }
+        res
+    }
+
+    // number of edges between the root and the first node holding `value`
+    pub fn depth_of(&self, value: &T) -> Option<usize> {
+        let mut depth = 0;
+        let mut node_ptr = self.root;
+        unsafe {
+            while !node_ptr.is_null() {
+                match self.comparator.compare(&(*node_ptr).value, value) {
+                    Ordering::Less => node_ptr = (*node_ptr).right,
+                    Ordering::Equal => return Some(depth),
+                    Ordering::Greater => node_ptr = (*node_ptr).left,
+                }
+                depth += 1;
+            }
+        }
+        None
+    }
+
+    // the deepest node whose subtree holds both values: walking down from the
+    // root, it is the first node not having both of them on the same side
+    pub fn lca(&self, a: &T, b: &T) -> Option<&T> {
+        let node_ptr = self.lca_node(a, b);
+        if node_ptr.is_null() {
+            None
+        } else {
+            unsafe { Some(&(*node_ptr).value) }
+        }
+    }
+
+    // values on the way from the root down to the first node holding `value`,
+    // both ends included; empty if it is not in the tree
+    pub fn path_to(&self, value: &T) -> Vec<&T> {
+        let mut res = Vec::new();
+        let mut node_ptr = self.find(value);
+        unsafe {
+            while !node_ptr.is_null() {
+                res.push(&(*node_ptr).value);
+                node_ptr = (*node_ptr).parent;
+            }
+        }
+        res.reverse();
+        res
+    }
+
+    // number of edges between the nodes holding `a` and `b`, through their lowest common ancestor
+    pub fn distance(&self, a: &T, b: &T) -> Option<usize> {
+        let lca_ptr = self.lca_node(a, b);
+        if lca_ptr.is_null() {
+            return None;
+        }
+        unsafe {
+            Some(Self::edges_up(self.find(a), lca_ptr) + Self::edges_up(self.find(b), lca_ptr))
+        }
+    }
+
+    // whether `a` sits strictly above `b`, on the path from the root to it
+    pub fn is_ancestor(&self, a: &T, b: &T) -> bool {
+        let node_ptr = self.find(b);
+        if node_ptr.is_null() {
+            return false;
+        }
+        unsafe {
+            let mut parent_ptr = (*node_ptr).parent;
+            while !parent_ptr.is_null() {
+                if self.comparator.compare(&(*parent_ptr).value, a) == Ordering::Equal {
+                    return true;
+                }
+                parent_ptr = (*parent_ptr).parent;
+            }
+        }
+        false
+    }
+
+    // null unless both values are in the tree
+    fn lca_node(&self, a: &T, b: &T) -> *mut Node<T> {
+        if self.find(a).is_null() || self.find(b).is_null() {
+            return null_mut();
+        }
+        let (low, high) = match self.comparator.compare(a, b) {
+            Ordering::Greater => (b, a),
+            _ => (a, b),
+        };
+        let mut node_ptr = self.root;
+        unsafe {
+            loop {
+                let value = &(*node_ptr).value;
+                if self.comparator.compare(value, low) == Ordering::Less {
+                    node_ptr = (*node_ptr).right;
+                } else if self.comparator.compare(value, high) == Ordering::Greater {
+                    node_ptr = (*node_ptr).left;
+                } else {
+                    return node_ptr;
+                }
+            }
+        }
+    }
+
+    // parent links followed from `from` until reaching its ancestor `to`
+    unsafe fn edges_up(from: *mut Node<T>, to: *mut Node<T>) -> usize {
+        let mut res = 0;
+        let mut node_ptr = from;
+        while node_ptr != to {
+            node_ptr = (*node_ptr).parent;
+            res += 1;
+        }
+        res
+    }
+}
+
+pub struct Range<'a, T> {
+    node: *mut Node<T>,
+    // last node inside the range
+    last: *mut Node<T>,
+    taken: usize,
+    tree: PhantomData<&'a Node<T>>,
 }
 
-impl<T> Drop for BinarySearchTree<T> 
+impl<'a, T> Iterator for Range<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.node.is_null() {
+            return None;
+        }
+        unsafe {
+            let node = &*self.node;
+            let res = node.occurrence(self.taken);
+            self.taken += 1;
+            if self.taken == node.count() {
+                self.node = if self.node == self.last { null_mut() } else { node.next_node() };
+                self.taken = 0;
+            }
+            Some(res)
+        }
+    }
+}
+
+impl<T, C> BinarySearchTree<T, C>
 where
-    T: Copy + Ord
+    C: Comparator<T>
+{
+    // detaches the leftmost node, which never has a left child
+    fn take_min(&mut self) -> Option<T> {
+        if self.root.is_null() {
+            return None;
+        }
+        unsafe {
+            let node_ptr = (*self.root).go_left_recursively() as *const Node<T> as *mut Node<T>;
+            self.num_elements -= 1;
+            // the oldest occurrence goes first, and the next oldest takes its place
+            if let Some(next) = (*node_ptr).repeats.pop_front() {
+                Node::update_sizes(node_ptr, null_mut());
+                return Some(std::mem::replace(&mut (*node_ptr).value, next));
+            }
+            let parent_ptr = (*node_ptr).parent;
+            let child_ptr = (*node_ptr).right;
+            if !child_ptr.is_null() {
+                (*child_ptr).parent = parent_ptr;
+            }
+            if parent_ptr.is_null() {
+                self.root = child_ptr;
+            } else {
+                (*parent_ptr).left = child_ptr;
+                Node::update_sizes(parent_ptr, null_mut());
+            }
+            Some(Node::into_value(node_ptr))
+        }
+    }
+
+    fn take_max(&mut self) -> Option<T> {
+        if self.root.is_null() {
+            return None;
+        }
+        unsafe {
+            let node_ptr = (*self.root).go_right_recursively() as *const Node<T> as *mut Node<T>;
+            self.num_elements -= 1;
+            if let Some(last) = (*node_ptr).repeats.pop_back() {
+                Node::update_sizes(node_ptr, null_mut());
+                return Some(last);
+            }
+            let parent_ptr = (*node_ptr).parent;
+            let child_ptr = (*node_ptr).left;
+            if !child_ptr.is_null() {
+                (*child_ptr).parent = parent_ptr;
+            }
+            if parent_ptr.is_null() {
+                self.root = child_ptr;
+            } else {
+                (*parent_ptr).right = child_ptr;
+                Node::update_sizes(parent_ptr, null_mut());
+            }
+            Some(Node::into_value(node_ptr))
+        }
+    }
+}
+
+pub struct Iter<'a, T> {
+    front: *mut Node<T>,
+    back: *mut Node<T>,
+    // occurrences of the current front/back node already handed out
+    front_taken: usize,
+    back_taken: usize,
+    len: usize,
+    tree: PhantomData<&'a Node<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.len == 0 {
+            return None;
+        }
+        unsafe {
+            let node = &*self.front;
+            let res = node.occurrence(self.front_taken);
+            self.front_taken += 1;
+            if self.front_taken == node.count() {
+                self.front = node.next_node();
+                self.front_taken = 0;
+            }
+            self.len -= 1;
+            Some(res)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.len == 0 {
+            return None;
+        }
+        unsafe {
+            let node = &*self.back;
+            self.back_taken += 1;
+            let res = node.occurrence(node.count() - self.back_taken);
+            if self.back_taken == node.count() {
+                self.back = node.prev_node();
+                self.back_taken = 0;
+            }
+            self.len -= 1;
+            Some(res)
+        }
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+pub struct PreorderIter<'a, T> {
+    node: *mut Node<T>,
+    // occurrences of `node` already handed out
+    taken: usize,
+    tree: PhantomData<&'a Node<T>>,
+}
+
+impl<'a, T> Iterator for PreorderIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.node.is_null() {
+            return None;
+        }
+        unsafe {
+            let node = &*self.node;
+            let res = node.occurrence(self.taken);
+            self.taken += 1;
+            if self.taken == node.count() {
+                self.node = node.preorder_next();
+                self.taken = 0;
+            }
+            Some(res)
+        }
+    }
+}
+
+pub struct PostorderIter<'a, T> {
+    node: *mut Node<T>,
+    taken: usize,
+    tree: PhantomData<&'a Node<T>>,
+}
+
+impl<'a, T> Iterator for PostorderIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.node.is_null() {
+            return None;
+        }
+        unsafe {
+            let node = &*self.node;
+            let res = node.occurrence(self.taken);
+            self.taken += 1;
+            if self.taken == node.count() {
+                self.node = node.postorder_next();
+                self.taken = 0;
+            }
+            Some(res)
+        }
+    }
+}
+
+pub struct LevelOrderIter<'a, T> {
+    queue: Queue<*mut Node<T>>,
+    // node whose occurrences are being handed out, its children already queued
+    node: *mut Node<T>,
+    taken: usize,
+    tree: PhantomData<&'a Node<T>>,
+}
+
+impl<'a, T> Iterator for LevelOrderIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        unsafe {
+            if self.node.is_null() {
+                self.node = self.queue.pop()?;
+                for child in [(*self.node).left, (*self.node).right] {
+                    if !child.is_null() {
+                        self.queue.push(child);
+                    }
+                }
+            }
+            let node = &*self.node;
+            let res = node.occurrence(self.taken);
+            self.taken += 1;
+            if self.taken == node.count() {
+                self.node = null_mut();
+                self.taken = 0;
+            }
+            Some(res)
+        }
+    }
+}
+
+pub struct IntoIter<T, C = NaturalOrder>
+where
+    C: Comparator<T>
+{
+    tree: BinarySearchTree<T, C>,
+}
+
+impl<T, C> Iterator for IntoIter<T, C>
+where
+    C: Comparator<T>
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.tree.take_min()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.tree.num_elements, Some(self.tree.num_elements))
+    }
+}
+
+impl<T, C> DoubleEndedIterator for IntoIter<T, C>
+where
+    C: Comparator<T>
+{
+    fn next_back(&mut self) -> Option<T> {
+        self.tree.take_max()
+    }
+}
+
+impl<T, C> ExactSizeIterator for IntoIter<T, C>
+where
+    C: Comparator<T>
+{}
+
+impl<'a, T, C> IntoIterator for &'a BinarySearchTree<T, C>
+where
+    C: Comparator<T>
+{
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T, C> IntoIterator for BinarySearchTree<T, C>
+where
+    C: Comparator<T>
+{
+    type Item = T;
+    type IntoIter = IntoIter<T, C>;
+
+    fn into_iter(self) -> IntoIter<T, C> {
+        IntoIter { tree: self }
+    }
+}
+
+impl<T, C> Drop for BinarySearchTree<T, C>
+where
+    C: Comparator<T>
 {
     fn drop(&mut self) {
         if !self.root.is_null() {
-            unsafe { drop_in_place(self.root); }
+            unsafe { Node::destroy(self.root); }
         }
     }
 }
 
-impl<T> fmt::Display for BinarySearchTree<T> 
+impl<T, C> fmt::Display for BinarySearchTree<T, C>
 where
-    T: Copy + Ord + fmt::Display
+    T: fmt::Display,
+    C: Comparator<T>
 {
     fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.num_elements == 0 {
@@ -405,3 +1916,87 @@ where
         }
     }
 }
+
+impl<T, C> fmt::Debug for BinarySearchTree<T, C>
+where
+    T: fmt::Debug,
+    C: Comparator<T>
+{
+    fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+// copies every node, so the clone has the same shape as well as the same values
+impl<T, C> Clone for BinarySearchTree<T, C>
+where
+    T: Clone,
+    C: Comparator<T> + Clone
+{
+    fn clone(&self) -> Self {
+        BinarySearchTree {
+            root: Node::clone_subtree(self.root, null_mut()),
+            num_elements: self.num_elements,
+            multiset: self.multiset,
+            comparator: self.comparator.clone(),
+        }
+    }
+}
+
+// trees are equal when they hold the same values the same number of times,
+// whatever order they were inserted in; "the same" is decided by the comparator
+// of the left-hand tree, like in `diff`, and `same_shape` compares the layout too
+impl<T, C> PartialEq for BinarySearchTree<T, C>
+where
+    C: Comparator<T>
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.num_elements == other.num_elements &&
+            self.iter().zip(other.iter()).all(|(a, b)| self.comparator.compare(a, b) == Ordering::Equal)
+    }
+}
+
+impl<T, C> Eq for BinarySearchTree<T, C>
+where
+    C: Comparator<T>
+{}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // equal neighbours are accepted, as separate nodes or as one node's repeats
+    #[test]
+    fn from_sorted_keeps_duplicates() {
+        let values = [1, 2, 2, 2, 3, 5, 5];
+        let set = BinarySearchTree::from_sorted(&values).unwrap();
+        set.validate().unwrap();
+        assert_eq!(set.count(), 7);
+        assert_eq!(set.count_of(&2), 3);
+        assert_eq!(set.infix_traverse(), values.iter().collect::<Vec<_>>());
+        let multiset = BinarySearchTree::multiset_from_sorted(&values).unwrap();
+        multiset.validate().unwrap();
+        assert_eq!(multiset.count(), 7);
+        assert_eq!(multiset.count_of(&2), 3);
+        assert_eq!(multiset.infix_traverse(), values.iter().collect::<Vec<_>>());
+        assert_eq!(multiset.infix_traverse_distinct(), [&1, &2, &3, &5]);
+        assert!(BinarySearchTree::from_sorted(&[1, 3, 2]).is_err());
+        assert!(BinarySearchTree::multiset_from_sorted(&[2, 2, 1]).is_err());
+    }
+
+    // a multiset built from sorted input behaves like one built by inserting
+    #[test]
+    fn multiset_from_sorted_matches_inserts() {
+        let values = [0, 0, 1, 1, 1, 4, 4, 9];
+        let mut built = BinarySearchTree::multiset_from_sorted(&values).unwrap();
+        let mut inserted = BinarySearchTree::new_multiset();
+        for value in values {
+            inserted.insert(value);
+        }
+        assert!(built == inserted);
+        assert_eq!(built.remove_all(&1), 3);
+        assert_eq!(inserted.remove_all(&1), 3);
+        assert!(built == inserted);
+        built.validate().unwrap();
+    }
+}
@@ -0,0 +1,410 @@
+use std::ptr::null_mut;
+use std::cmp::Ordering;
+use std::fmt;
+use crate::bst::Node;
+
+// nodes of the map are ordered by key only
+struct Pair<K, V> {
+    key: K,
+    value: V,
+}
+
+impl<K: Ord, V> PartialEq for Pair<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K: Ord, V> Eq for Pair<K, V> {}
+
+impl<K: Ord, V> PartialOrd for Pair<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, V> Ord for Pair<K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+impl<K, V> fmt::Display for Pair<K, V>
+where
+    K: fmt::Display,
+    V: fmt::Display
+{
+    fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.value)
+    }
+}
+
+pub struct BstMap<K, V>
+where
+    K: Ord
+{
+    root: *mut Node<Pair<K, V>>,
+    num_elements: usize,
+}
+
+impl<K, V> BstMap<K, V>
+where
+    K: Ord
+{
+    pub fn new() -> Self {
+        BstMap {
+            root: null_mut(),
+            num_elements: 0,
+        }
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(old) = self.get_mut(&key) {
+            return Some(std::mem::replace(old, value));
+        }
+        self.insert_new(key, value);
+        None
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        if self.root.is_null() {
+            assert_eq!(self.num_elements, 0);
+            None
+        } else {
+            unsafe {
+                (*self.root).search_by(|pair| pair.key.cmp(key)).map(|node| &node.value.value)
+            }
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        if self.root.is_null() {
+            assert_eq!(self.num_elements, 0);
+            None
+        } else {
+            unsafe {
+                (*self.root).search_by_mut(|pair| pair.key.cmp(key)).map(|node| &mut node.value.value)
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let node_ptr = self.find(key);
+        if node_ptr.is_null() {
+            None
+        } else {
+            unsafe { Some(self.remove_node(node_ptr).value) }
+        }
+    }
+
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        let node_ptr = self.find(&key);
+        if node_ptr.is_null() {
+            Entry::Vacant(VacantEntry { key, map: self })
+        } else {
+            Entry::Occupied(OccupiedEntry { node: node_ptr, map: self })
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.num_elements
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.num_elements == 0
+    }
+
+    pub fn min(&self) -> Option<(&K, &V)> {
+        if self.root.is_null() {
+            None
+        } else {
+            unsafe {
+                let pair = &(*self.root).go_left_recursively().value;
+                Some((&pair.key, &pair.value))
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn max(&self) -> Option<(&K, &V)> {
+        if self.root.is_null() {
+            None
+        } else {
+            unsafe {
+                let pair = &(*self.root).go_right_recursively().value;
+                Some((&pair.key, &pair.value))
+            }
+        }
+    }
+
+    fn find(&self, key: &K) -> *mut Node<Pair<K, V>> {
+        if self.root.is_null() {
+            return null_mut();
+        }
+        unsafe {
+            match (*self.root).search_by(|pair| pair.key.cmp(key)) {
+                Some(node) => node as *const Node<Pair<K, V>> as *mut Node<Pair<K, V>>,
+                None => null_mut(),
+            }
+        }
+    }
+
+    unsafe fn remove_node(&mut self, node_ptr: *mut Node<Pair<K, V>>) -> Pair<K, V> {
+        self.num_elements -= 1;
+        if node_ptr == self.root &&
+            !(!(*self.root).left.is_null() && !(*self.root).right.is_null())
+        {
+            if (*self.root).left.is_null() {
+                self.root = (*self.root).right;
+            } else {
+                self.root = (*self.root).left;
+            }
+            if !self.root.is_null() {
+                (*self.root).parent = null_mut();
+            }
+            Node::into_value(node_ptr)
+        } else {
+            // the node itself is the match, so del_by stops right there
+            let parent_ptr = (*node_ptr).parent;
+            let pair = Node::del_by(node_ptr, |_| Ordering::Equal).unwrap();
+            Node::update_sizes(parent_ptr, null_mut());
+            pair
+        }
+    }
+
+    // the caller has checked that `key` is not in the map yet
+    fn insert_new(&mut self, key: K, value: V) -> *mut Node<Pair<K, V>> {
+        let new_ptr = Node::new_ptr(Pair { key, value });
+        if self.root.is_null() {
+            self.root = new_ptr;
+        } else {
+            unsafe {
+                (*self.root).add(&mut *new_ptr);
+            }
+        }
+        self.num_elements += 1;
+        new_ptr
+    }
+}
+
+impl<K, V> Drop for BstMap<K, V>
+where
+    K: Ord
+{
+    fn drop(&mut self) {
+        if !self.root.is_null() {
+            unsafe { Node::destroy(self.root); }
+        }
+    }
+}
+
+impl<K, V> fmt::Display for BstMap<K, V>
+where
+    K: Ord + fmt::Display,
+    V: fmt::Display
+{
+    fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.num_elements == 0 {
+            writeln!(f, "empty map")
+        } else {
+            unsafe {
+                let mut out = String::new();
+                (*self.root).print_traverse(&mut out, 0);
+                write!(f, "{}", &out[..out.len()-1])
+            }
+        }
+    }
+}
+
+pub enum Entry<'a, K, V>
+where
+    K: Ord
+{
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+pub struct OccupiedEntry<'a, K, V>
+where
+    K: Ord
+{
+    node: *mut Node<Pair<K, V>>,
+    #[allow(dead_code)]
+    map: &'a mut BstMap<K, V>,
+}
+
+pub struct VacantEntry<'a, K, V>
+where
+    K: Ord
+{
+    key: K,
+    map: &'a mut BstMap<K, V>,
+}
+
+impl<'a, K, V> Entry<'a, K, V>
+where
+    K: Ord
+{
+    #[allow(dead_code)]
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    pub fn or_insert_with<F>(self, default: F) -> &'a mut V
+    where
+        F: FnOnce() -> V
+    {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default
+    {
+        self.or_insert_with(V::default)
+    }
+
+    #[allow(dead_code)]
+    pub fn and_modify<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&mut V)
+    {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            },
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<'a, K, V> OccupiedEntry<'a, K, V>
+where
+    K: Ord
+{
+    pub fn key(&self) -> &K {
+        unsafe { &(*self.node).value.key }
+    }
+
+    pub fn get(&self) -> &V {
+        unsafe { &(*self.node).value.value }
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        unsafe { &mut (*self.node).value.value }
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        unsafe { &mut (*self.node).value.value }
+    }
+
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove(self) -> V {
+        unsafe { self.map.remove_node(self.node).value }
+    }
+}
+
+impl<'a, K, V> VacantEntry<'a, K, V>
+where
+    K: Ord
+{
+    #[allow(dead_code)]
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    #[allow(dead_code)]
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V {
+        let node_ptr = self.map.insert_new(self.key, value);
+        unsafe { &mut (*node_ptr).value.value }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word_counts() -> BstMap<String, usize> {
+        let mut words = BstMap::new();
+        for word in "the quick brown fox jumps over the lazy dog the end".split(' ') {
+            *words.entry(word.to_string()).or_insert_with(|| 0) += 1;
+        }
+        words
+    }
+
+    #[test]
+    fn lookups() {
+        let mut words = word_counts();
+        assert!(!words.is_empty());
+        assert!(words.contains_key(&"lazy".to_string()));
+        assert!(!words.contains_key(&"cat".to_string()));
+        assert_eq!(words.min(), Some((&"brown".to_string(), &1)));
+        assert_eq!(words.max(), Some((&"the".to_string(), &3)));
+        for key in ["brown", "dog", "end", "fox", "jumps", "lazy", "over", "quick", "the"] {
+            words.remove(&key.to_string()).unwrap();
+        }
+        assert!(words.is_empty());
+        assert_eq!(words.max(), None);
+    }
+
+    #[test]
+    fn entries() {
+        let mut words = word_counts();
+        *words.entry("cat".to_string()).or_insert(0) += 1;
+        *words.entry("cow".to_string()).or_default() += 2;
+        words.entry("the".to_string()).and_modify(|count| *count *= 10);
+        words.entry("yak".to_string()).and_modify(|count| *count *= 10);
+        assert_eq!(words.get(&"cat".to_string()), Some(&1));
+        assert_eq!(words.get(&"cow".to_string()), Some(&2));
+        assert_eq!(words.get(&"the".to_string()), Some(&30));
+        assert!(!words.contains_key(&"yak".to_string()));
+        assert_eq!(words.entry("quick".to_string()).key(), "quick");
+        match words.entry("quick".to_string()) {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.key(), "quick");
+                *entry.get_mut() += 1;
+                assert_eq!(entry.insert(*entry.get() + 1), 2);
+                assert_eq!(entry.remove(), 3);
+            },
+            Entry::Vacant(_) => panic!("quick is in the map"),
+        }
+        match words.entry("quick".to_string()) {
+            Entry::Occupied(_) => panic!("quick was removed"),
+            Entry::Vacant(entry) => {
+                assert_eq!(entry.key(), "quick");
+                assert_eq!(entry.into_key(), "quick");
+            },
+        }
+        assert!(!words.contains_key(&"quick".to_string()));
+    }
+}
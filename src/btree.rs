@@ -0,0 +1,626 @@
+use std::ptr::{ null_mut, drop_in_place };
+use std::alloc::{ alloc, dealloc, Layout, handle_alloc_error };
+use std::ops::{ Bound, RangeBounds };
+use std::marker::PhantomData;
+use std::fmt;
+use crate::queue::Queue;
+
+// keys sit next to each other in one node, so a lookup touches a handful of
+// nodes instead of one per level of a binary tree
+struct Node<K, V> {
+    keys: Vec<K>,
+    values: Vec<V>,
+    // empty for a leaf, one more than `keys` otherwise
+    children: Vec<*mut Self>,
+}
+
+impl<K, V> Node<K, V> {
+    // room for the most keys a node can hold is reserved up front
+    fn new_ptr(min_degree: usize) -> *mut Self {
+        let layout = Layout::new::<Node<K, V>>();
+        unsafe {
+            let node_ptr = alloc(layout) as *mut Node<K, V>;
+            if node_ptr.is_null() {
+                handle_alloc_error(layout);
+            }
+            node_ptr.write(Node {
+                keys: Vec::with_capacity(2 * min_degree - 1),
+                values: Vec::with_capacity(2 * min_degree - 1),
+                children: Vec::new(),
+            });
+            node_ptr
+        }
+    }
+
+    // frees `node_ptr` after dropping its keys, values and children in place
+    unsafe fn destroy(node_ptr: *mut Self) {
+        drop_in_place(node_ptr);
+        dealloc(node_ptr as *mut u8, Layout::new::<Node<K, V>>());
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    fn height(&self) -> usize {
+        if self.is_leaf() {
+            1
+        } else {
+            unsafe { 1 + (*self.children[0]).height() }
+        }
+    }
+}
+
+impl<K, V> Node<K, V>
+where
+    K: Ord
+{
+    // position of the first key >= `key`
+    fn position(&self, key: &K) -> usize {
+        self.keys.partition_point(|k| k < key)
+    }
+
+    fn search(&self, key: &K) -> Option<(&Self, usize)> {
+        let idx = self.position(key);
+        if idx < self.keys.len() && self.keys[idx] == *key {
+            Some((self, idx))
+        } else if self.is_leaf() {
+            None
+        } else {
+            unsafe { (*self.children[idx]).search(key) }
+        }
+    }
+
+    // moves the upper half of the full child `idx` into a new right sibling
+    // and its median key up into this node
+    unsafe fn split_child(&mut self, idx: usize, min_degree: usize) {
+        let child = self.children[idx];
+        let sibling = Self::new_ptr(min_degree);
+        (*sibling).keys.extend((*child).keys.drain(min_degree..));
+        (*sibling).values.extend((*child).values.drain(min_degree..));
+        if !(*child).is_leaf() {
+            (*sibling).children.extend((*child).children.drain(min_degree..));
+        }
+        self.keys.insert(idx, (*child).keys.pop().unwrap());
+        self.values.insert(idx, (*child).values.pop().unwrap());
+        self.children.insert(idx + 1, sibling);
+    }
+
+    // this node is not full, so a split below always has room for the median
+    unsafe fn insert_non_full(&mut self, key: K, value: V, min_degree: usize) -> Option<V> {
+        let mut idx = self.position(&key);
+        if idx < self.keys.len() && self.keys[idx] == key {
+            return Some(std::mem::replace(&mut self.values[idx], value));
+        }
+        if self.is_leaf() {
+            self.keys.insert(idx, key);
+            self.values.insert(idx, value);
+            return None;
+        }
+        if (*self.children[idx]).keys.len() == 2 * min_degree - 1 {
+            self.split_child(idx, min_degree);
+            if self.keys[idx] == key {
+                return Some(std::mem::replace(&mut self.values[idx], value));
+            }
+            if self.keys[idx] < key {
+                idx += 1;
+            }
+        }
+        (*self.children[idx]).insert_non_full(key, value, min_degree)
+    }
+
+    // deletes `key` from this subtree; every node the walk enters has at least
+    // `min_degree` keys, so the deletion never leaves one short
+    unsafe fn remove(&mut self, key: &K, min_degree: usize) -> Option<(K, V)> {
+        let idx = self.position(key);
+        if idx < self.keys.len() && self.keys[idx] == *key {
+            if self.is_leaf() {
+                return Some((self.keys.remove(idx), self.values.remove(idx)));
+            }
+            // an inner key is swapped for its predecessor or successor, whichever
+            // child can spare one; with neither the two children are merged around it
+            if (*self.children[idx]).keys.len() >= min_degree {
+                let (k, v) = (*self.children[idx]).pop_max(min_degree);
+                let k = std::mem::replace(&mut self.keys[idx], k);
+                let v = std::mem::replace(&mut self.values[idx], v);
+                return Some((k, v));
+            }
+            if (*self.children[idx + 1]).keys.len() >= min_degree {
+                let (k, v) = (*self.children[idx + 1]).pop_min(min_degree);
+                let k = std::mem::replace(&mut self.keys[idx], k);
+                let v = std::mem::replace(&mut self.values[idx], v);
+                return Some((k, v));
+            }
+            self.merge_children(idx);
+            return (*self.children[idx]).remove(key, min_degree);
+        }
+        if self.is_leaf() {
+            return None;
+        }
+        let idx = self.fill_child(idx, min_degree);
+        (*self.children[idx]).remove(key, min_degree)
+    }
+}
+
+impl<K, V> Node<K, V> {
+    unsafe fn pop_max(&mut self, min_degree: usize) -> (K, V) {
+        if self.is_leaf() {
+            return (self.keys.pop().unwrap(), self.values.pop().unwrap());
+        }
+        let idx = self.fill_child(self.keys.len(), min_degree);
+        (*self.children[idx]).pop_max(min_degree)
+    }
+
+    unsafe fn pop_min(&mut self, min_degree: usize) -> (K, V) {
+        if self.is_leaf() {
+            return (self.keys.remove(0), self.values.remove(0));
+        }
+        let idx = self.fill_child(0, min_degree);
+        (*self.children[idx]).pop_min(min_degree)
+    }
+
+    // makes sure child `idx` has at least `min_degree` keys before the walk enters
+    // it, borrowing through this node from a sibling or merging with one; returns
+    // where the child ended up
+    unsafe fn fill_child(&mut self, idx: usize, min_degree: usize) -> usize {
+        if (*self.children[idx]).keys.len() >= min_degree {
+            return idx;
+        }
+        if idx > 0 && (*self.children[idx - 1]).keys.len() >= min_degree {
+            self.borrow_from_prev(idx);
+            idx
+        } else if idx < self.keys.len() && (*self.children[idx + 1]).keys.len() >= min_degree {
+            self.borrow_from_next(idx);
+            idx
+        } else if idx < self.keys.len() {
+            self.merge_children(idx);
+            idx
+        } else {
+            self.merge_children(idx - 1);
+            idx - 1
+        }
+    }
+
+    // the separating key drops into child `idx`, the left sibling's last key replaces it
+    unsafe fn borrow_from_prev(&mut self, idx: usize) {
+        let child = self.children[idx];
+        let sibling = self.children[idx - 1];
+        let key = std::mem::replace(&mut self.keys[idx - 1], (*sibling).keys.pop().unwrap());
+        let value = std::mem::replace(&mut self.values[idx - 1], (*sibling).values.pop().unwrap());
+        (*child).keys.insert(0, key);
+        (*child).values.insert(0, value);
+        if !(*sibling).is_leaf() {
+            (*child).children.insert(0, (*sibling).children.pop().unwrap());
+        }
+    }
+
+    unsafe fn borrow_from_next(&mut self, idx: usize) {
+        let child = self.children[idx];
+        let sibling = self.children[idx + 1];
+        let key = std::mem::replace(&mut self.keys[idx], (*sibling).keys.remove(0));
+        let value = std::mem::replace(&mut self.values[idx], (*sibling).values.remove(0));
+        (*child).keys.push(key);
+        (*child).values.push(value);
+        if !(*sibling).is_leaf() {
+            (*child).children.push((*sibling).children.remove(0));
+        }
+    }
+
+    // folds key `idx` and child `idx + 1` into child `idx` and frees the emptied sibling
+    unsafe fn merge_children(&mut self, idx: usize) {
+        let child = self.children[idx];
+        let sibling = self.children.remove(idx + 1);
+        (*child).keys.push(self.keys.remove(idx));
+        (*child).values.push(self.values.remove(idx));
+        (*child).keys.append(&mut (*sibling).keys);
+        (*child).values.append(&mut (*sibling).values);
+        (*child).children.append(&mut (*sibling).children);
+        Self::destroy(sibling);
+    }
+}
+
+impl<K, V> Drop for Node<K, V> {
+    fn drop(&mut self) {
+        unsafe {
+            for &child in &self.children {
+                Node::destroy(child);
+            }
+        }
+    }
+}
+
+pub struct BTree<K, V>
+where
+    K: Ord
+{
+    root: *mut Node<K, V>,
+    num_elements: usize,
+    // every node but the root holds between `min_degree - 1` and `2 * min_degree - 1` keys
+    min_degree: usize,
+}
+
+impl<K, V> BTree<K, V>
+where
+    K: Ord
+{
+    pub fn new() -> Self {
+        Self::with_min_degree(6)
+    }
+
+    pub fn with_min_degree(min_degree: usize) -> Self {
+        assert!(min_degree >= 2, "a B-tree needs a minimum degree of at least 2");
+        BTree {
+            root: null_mut(),
+            num_elements: 0,
+            min_degree,
+        }
+    }
+
+    pub fn min_degree(&self) -> usize {
+        self.min_degree
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        unsafe {
+            if self.root.is_null() {
+                self.root = Node::new_ptr(self.min_degree);
+            } else if (*self.root).keys.len() == 2 * self.min_degree - 1 {
+                // a full root is split first, which is the only way the tree grows taller
+                let new_root = Node::new_ptr(self.min_degree);
+                (*new_root).children.push(self.root);
+                (*new_root).split_child(0, self.min_degree);
+                self.root = new_root;
+            }
+            let res = (*self.root).insert_non_full(key, value, self.min_degree);
+            if res.is_none() {
+                self.num_elements += 1;
+            }
+            res
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        if self.root.is_null() {
+            return None;
+        }
+        unsafe {
+            (*self.root).search(key).map(|(node, idx)| &node.values[idx])
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        if self.root.is_null() {
+            return None;
+        }
+        let mut node_ptr = self.root;
+        unsafe {
+            loop {
+                let node = &mut *node_ptr;
+                let idx = node.position(key);
+                if idx < node.keys.len() && node.keys[idx] == *key {
+                    return Some(&mut node.values[idx]);
+                }
+                if node.is_leaf() {
+                    return None;
+                }
+                node_ptr = node.children[idx];
+            }
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        if self.root.is_null() {
+            assert_eq!(self.num_elements, 0);
+            return None;
+        }
+        unsafe {
+            let res = (*self.root).remove(key, self.min_degree);
+            if (*self.root).keys.is_empty() {
+                // a root emptied by a merge hands over to its only child
+                let old_root = self.root;
+                self.root = if (*old_root).is_leaf() { null_mut() } else { (*old_root).children.pop().unwrap() };
+                Node::destroy(old_root);
+            }
+            res.map(|(_, value)| {
+                self.num_elements -= 1;
+                value
+            })
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.num_elements
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_elements == 0
+    }
+
+    // number of node levels, all leaves sit on the last one
+    pub fn height(&self) -> usize {
+        if self.root.is_null() {
+            0
+        } else {
+            unsafe { (*self.root).height() }
+        }
+    }
+
+    pub fn min(&self) -> Option<(&K, &V)> {
+        self.iter().next()
+    }
+
+    pub fn max(&self) -> Option<(&K, &V)> {
+        if self.root.is_null() {
+            return None;
+        }
+        unsafe {
+            let mut node_ptr = self.root;
+            while !(*node_ptr).is_leaf() {
+                node_ptr = *(*node_ptr).children.last().unwrap();
+            }
+            let node = &*node_ptr;
+            Some((node.keys.last().unwrap(), node.values.last().unwrap()))
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let mut iter = Iter {
+            stack: Vec::new(),
+            last: null_mut(),
+            tree: PhantomData,
+        };
+        if !self.root.is_null() {
+            iter.push_left(self.root);
+        }
+        iter
+    }
+
+    // finds the first and last entries in `range` by descending from the root once
+    // for each, and the iterator then stops at the last one
+    pub fn range<R>(&self, range: R) -> Iter<'_, K, V>
+    where
+        R: RangeBounds<K>
+    {
+        let empty = Iter {
+            stack: Vec::new(),
+            last: null_mut(),
+            tree: PhantomData,
+        };
+        if self.root.is_null() {
+            return empty;
+        }
+        let mut stack = Vec::new();
+        let mut last: *const K = null_mut::<K>();
+        unsafe {
+            // the walk towards the start leaves every ancestor on the stack pointing
+            // at its next key; the walk towards the end remembers the last key in range
+            let mut node_ptr = self.root;
+            loop {
+                let node = &*node_ptr;
+                let idx = match range.start_bound() {
+                    Bound::Included(start) => node.keys.partition_point(|k| k < start),
+                    Bound::Excluded(start) => node.keys.partition_point(|k| k <= start),
+                    Bound::Unbounded => 0,
+                };
+                stack.push((node_ptr, idx));
+                if node.is_leaf() {
+                    break;
+                }
+                node_ptr = node.children[idx];
+            }
+            let mut node_ptr = self.root;
+            loop {
+                let node = &*node_ptr;
+                let idx = match range.end_bound() {
+                    Bound::Included(end) => node.keys.partition_point(|k| k <= end),
+                    Bound::Excluded(end) => node.keys.partition_point(|k| k < end),
+                    Bound::Unbounded => node.keys.len(),
+                };
+                if idx > 0 {
+                    last = &node.keys[idx - 1];
+                }
+                if node.is_leaf() {
+                    break;
+                }
+                node_ptr = node.children[idx];
+            }
+        }
+        let res = Iter {
+            stack,
+            last,
+            tree: PhantomData,
+        };
+        let first = res.clone().next().map(|(k, _)| k as *const K);
+        match first {
+            Some(first) if !last.is_null() && unsafe { *first <= *last } => res,
+            _ => empty,
+        }
+    }
+
+    // checks key order, node occupancy, that all leaves are equally deep and the element counter
+    pub fn validate(&self) -> Result<(), BTreeViolation<&K>> {
+        let mut actual = 0;
+        let mut leaf_depth = None;
+        unsafe {
+            // every node is visited along with its depth and the keys bounding it from below and above
+            let mut stack = Vec::new();
+            if !self.root.is_null() {
+                stack.push((self.root, 0, None, None));
+            }
+            while let Some((node_ptr, depth, low, high)) = stack.pop() {
+                let node = &*node_ptr;
+                actual += node.keys.len();
+                let fewest = if node_ptr == self.root { 1 } else { self.min_degree - 1 };
+                if node.keys.len() < fewest || node.keys.len() > 2 * self.min_degree - 1 ||
+                    node.values.len() != node.keys.len() ||
+                    (!node.is_leaf() && node.children.len() != node.keys.len() + 1)
+                {
+                    return Err(BTreeViolation::Occupancy { depth, keys: node.keys.len() });
+                }
+                for (i, key) in node.keys.iter().enumerate() {
+                    let below = if i == 0 { low } else { Some(&node.keys[i - 1]) };
+                    if let Some(bound) = below.filter(|bound| key <= *bound).or(high.filter(|bound| key >= *bound)) {
+                        return Err(BTreeViolation::Order { node: key, bound });
+                    }
+                }
+                if node.is_leaf() {
+                    if *leaf_depth.get_or_insert(depth) != depth {
+                        return Err(BTreeViolation::LeafDepth { node: &node.keys[0] });
+                    }
+                    continue;
+                }
+                for (i, &child) in node.children.iter().enumerate() {
+                    let child_low = if i == 0 { low } else { Some(&node.keys[i - 1]) };
+                    let child_high = if i == node.keys.len() { high } else { Some(&node.keys[i]) };
+                    stack.push((child, depth + 1, child_low, child_high));
+                }
+            }
+        }
+        if actual != self.num_elements {
+            return Err(BTreeViolation::Count { recorded: self.num_elements, actual });
+        }
+        Ok(())
+    }
+}
+
+// ancestors waiting to hand out their next key sit on a stack, each with that key's index
+pub struct Iter<'a, K, V> {
+    stack: Vec<(*mut Node<K, V>, usize)>,
+    // a range scan stops after this key
+    last: *const K,
+    tree: PhantomData<&'a Node<K, V>>,
+}
+
+impl<'a, K, V> Iter<'a, K, V> {
+    fn push_left(&mut self, node: *mut Node<K, V>) {
+        let mut node_ptr = node;
+        unsafe {
+            loop {
+                self.stack.push((node_ptr, 0));
+                if (*node_ptr).is_leaf() {
+                    break;
+                }
+                node_ptr = *(*node_ptr).children.first().unwrap();
+            }
+        }
+    }
+}
+
+impl<'a, K, V> Clone for Iter<'a, K, V> {
+    fn clone(&self) -> Self {
+        Iter {
+            stack: self.stack.clone(),
+            last: self.last,
+            tree: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        unsafe {
+            loop {
+                let (node_ptr, idx) = *self.stack.last()?;
+                let node = &*node_ptr;
+                if idx == node.keys.len() {
+                    self.stack.pop();
+                    continue;
+                }
+                self.stack.last_mut().unwrap().1 += 1;
+                if !node.is_leaf() {
+                    self.push_left(node.children[idx + 1]);
+                }
+                let key = &node.keys[idx];
+                if std::ptr::eq(key, self.last) {
+                    self.stack.clear();
+                }
+                return Some((key, &node.values[idx]));
+            }
+        }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a BTree<K, V>
+where
+    K: Ord
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<K, V> Drop for BTree<K, V>
+where
+    K: Ord
+{
+    fn drop(&mut self) {
+        if !self.root.is_null() {
+            unsafe { Node::destroy(self.root); }
+        }
+    }
+}
+
+// one line per level, every node's keys in brackets
+impl<K, V> fmt::Display for BTree<K, V>
+where
+    K: Ord + fmt::Display
+{
+    fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.num_elements == 0 {
+            return writeln!(f, "empty tree");
+        }
+        let mut lines = Vec::new();
+        let mut queue = Queue::new();
+        queue.push(self.root);
+        while !queue.is_empty() {
+            let mut level = Vec::with_capacity(queue.len());
+            for _ in 0..queue.len() {
+                let node_ptr = queue.pop().unwrap();
+                unsafe {
+                    let keys: Vec<String> = (*node_ptr).keys.iter().map(|key| key.to_string()).collect();
+                    level.push(format!("[{}]", keys.join(" ")));
+                    for &child in &(*node_ptr).children {
+                        queue.push(child);
+                    }
+                }
+            }
+            lines.push(level.join(" "));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+// the first broken invariant `BTree::validate` comes across
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BTreeViolation<K> {
+    // a node at `depth` holds too few or too many keys for the minimum degree
+    Occupancy { depth: usize, keys: usize },
+    // `node` lies on the wrong side of the key `bound` above or beside it
+    Order { node: K, bound: K },
+    // the leaf holding `node` is not as deep as the others
+    LeafDepth { node: K },
+    Count { recorded: usize, actual: usize },
+}
+
+impl<K> fmt::Display for BTreeViolation<K>
+where
+    K: fmt::Display
+{
+    fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BTreeViolation::Occupancy { depth, keys } => write!(f, "node at depth {} holds {} keys", depth, keys),
+            BTreeViolation::Order { node, bound } => write!(f, "{} is on the wrong side of {}", node, bound),
+            BTreeViolation::LeafDepth { node } => write!(f, "leaf holding {} is out of level", node),
+            BTreeViolation::Count { recorded, actual } => write!(f, "tree records {} keys but holds {}", recorded, actual),
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use std::ptr::null_mut;
+use std::alloc::{ alloc, dealloc, Layout, handle_alloc_error };
+use std::marker::PhantomData;
+
+struct Node<T> {
+    value: T,
+    // towards the front
+    prev: *mut Self,
+    // towards the back
+    next: *mut Self,
+}
+
+impl<T> Node<T> {
+    fn new_ptr(value: T) -> *mut Self {
+        let layout = Layout::new::<Node<T>>();
+        unsafe {
+            let node_ptr = alloc(layout) as *mut Node<T>;
+            if node_ptr.is_null() {
+                handle_alloc_error(layout);
+            }
+            node_ptr.write(Node {
+                value,
+                prev: null_mut(),
+                next: null_mut(),
+            });
+            node_ptr
+        }
+    }
+
+    // frees a node unlinked from both of its neighbours and returns its value
+    unsafe fn into_value(node_ptr: *mut Self) -> T {
+        let value = std::ptr::read(&(*node_ptr).value);
+        dealloc(node_ptr as *mut u8, Layout::new::<Node<T>>());
+        value
+    }
+}
+
+// a doubly linked list open at both ends
+pub struct Deque<T> {
+    front: *mut Node<T>,
+    back: *mut Node<T>,
+    len: usize,
+}
+
+impl<T> Deque<T> {
+    pub fn new() -> Self {
+        Deque {
+            front: null_mut(),
+            back: null_mut(),
+            len: 0,
+        }
+    }
+
+    pub fn push_front(&mut self, value: T) {
+        let new_ptr = Node::new_ptr(value);
+        if self.len == 0 {
+            self.back = new_ptr;
+        } else {
+            unsafe {
+                (*new_ptr).next = self.front;
+                (*self.front).prev = new_ptr;
+            }
+        }
+        self.front = new_ptr;
+        self.len += 1;
+    }
+
+    pub fn push_back(&mut self, value: T) {
+        let new_ptr = Node::new_ptr(value);
+        if self.len == 0 {
+            self.front = new_ptr;
+        } else {
+            unsafe {
+                (*new_ptr).prev = self.back;
+                (*self.back).next = new_ptr;
+            }
+        }
+        self.back = new_ptr;
+        self.len += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        unsafe {
+            let node_ptr = self.front;
+            self.front = (*node_ptr).next;
+            if self.front.is_null() {
+                self.back = null_mut();
+            } else {
+                (*self.front).prev = null_mut();
+            }
+            self.len -= 1;
+            Some(Node::into_value(node_ptr))
+        }
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        unsafe {
+            let node_ptr = self.back;
+            self.back = (*node_ptr).prev;
+            if self.back.is_null() {
+                self.front = null_mut();
+            } else {
+                (*self.back).next = null_mut();
+            }
+            self.len -= 1;
+            Some(Node::into_value(node_ptr))
+        }
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        if self.len == 0 {
+            None
+        } else {
+            unsafe { Some(&(*self.front).value) }
+        }
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        if self.len == 0 {
+            None
+        } else {
+            unsafe { Some(&(*self.back).value) }
+        }
+    }
+
+    // counts from the front; walks in from whichever end is closer
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len {
+            None
+        } else {
+            unsafe { Some(&(*self.node_at(index)).value) }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // moves the first `k` values to the back by relinking, without moving any value
+    pub fn rotate_left(&mut self, k: usize) {
+        assert!(k <= self.len, "cannot rotate {} values of a deque of {}", k, self.len);
+        if k == 0 || k == self.len {
+            return;
+        }
+        unsafe {
+            let new_front = self.node_at(k);
+            // close the ring, then open it again in front of `new_front`
+            (*self.back).next = self.front;
+            (*self.front).prev = self.back;
+            self.front = new_front;
+            self.back = (*new_front).prev;
+            (*self.front).prev = null_mut();
+            (*self.back).next = null_mut();
+        }
+    }
+
+    // moves the last `k` values to the front
+    pub fn rotate_right(&mut self, k: usize) {
+        assert!(k <= self.len, "cannot rotate {} values of a deque of {}", k, self.len);
+        self.rotate_left(self.len - k);
+    }
+
+    // front to back; `.rev()` goes back to front
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            front: self.front,
+            back: self.back,
+            len: self.len,
+            deque: PhantomData,
+        }
+    }
+
+    // `index` must be below `len`
+    fn node_at(&self, index: usize) -> *mut Node<T> {
+        unsafe {
+            if index < self.len / 2 {
+                let mut node_ptr = self.front;
+                for _ in 0..index {
+                    node_ptr = (*node_ptr).next;
+                }
+                node_ptr
+            } else {
+                let mut node_ptr = self.back;
+                for _ in index + 1..self.len {
+                    node_ptr = (*node_ptr).prev;
+                }
+                node_ptr
+            }
+        }
+    }
+}
+
+// the values not yet handed out lie between `front` and `back`, `len` of them
+pub struct Iter<'a, T> {
+    front: *mut Node<T>,
+    back: *mut Node<T>,
+    len: usize,
+    deque: PhantomData<&'a Node<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.len == 0 {
+            return None;
+        }
+        unsafe {
+            let node = &*self.front;
+            self.front = node.next;
+            self.len -= 1;
+            Some(&node.value)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.len == 0 {
+            return None;
+        }
+        unsafe {
+            let node = &*self.back;
+            self.back = node.prev;
+            self.len -= 1;
+            Some(&node.value)
+        }
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T> IntoIterator for &'a Deque<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T> Drop for Deque<T> {
+    fn drop(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+}
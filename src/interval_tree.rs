@@ -0,0 +1,656 @@
+use std::ptr::null_mut;
+use std::alloc::{ alloc, dealloc, Layout, handle_alloc_error };
+use std::cmp::{ Ordering, max };
+use std::marker::PhantomData;
+use std::fmt;
+
+// a closed interval [low, high], augmented with the largest `high` found in its subtree
+struct Node<T> {
+    low: T,
+    high: T,
+    max: T,
+    height: usize,
+    parent: *mut Self,
+    left: *mut Self,
+    right: *mut Self,
+}
+
+impl<T> Node<T>
+where
+    T: Ord + Clone
+{
+    fn new_ptr(low: T, high: T) -> *mut Self {
+        let layout = Layout::new::<Node<T>>();
+        unsafe {
+            let node_ptr = alloc(layout) as *mut Node<T>;
+            if node_ptr.is_null() {
+                handle_alloc_error(layout);
+            }
+            node_ptr.write(Node {
+                max: high.clone(),
+                low,
+                high,
+                height: 1,
+                parent: null_mut(),
+                left: null_mut(),
+                right: null_mut(),
+            });
+            node_ptr
+        }
+    }
+
+    // frees a single detached node, handing its interval back to the caller
+    unsafe fn into_interval(node_ptr: *mut Self) -> (T, T) {
+        let low = std::ptr::read(&(*node_ptr).low);
+        let high = std::ptr::read(&(*node_ptr).high);
+        std::ptr::drop_in_place(&mut (*node_ptr).max);
+        dealloc(node_ptr as *mut u8, Layout::new::<Node<T>>());
+        (low, high)
+    }
+
+    // intervals are ordered by their low end, then by their high end
+    fn cmp_interval(&self, low: &T, high: &T) -> Ordering {
+        self.low.cmp(low).then_with(|| self.high.cmp(high))
+    }
+
+    fn overlaps(&self, low: &T, high: &T) -> bool {
+        self.low <= *high && *low <= self.high
+    }
+
+    // the largest endpoint in this subtree, from its own interval and the children's maxima
+    fn expected_max(&self) -> &T {
+        let mut res = &self.high;
+        unsafe {
+            for child in [self.left, self.right] {
+                if !child.is_null() && (*child).max > *res {
+                    res = &(*child).max;
+                }
+            }
+        }
+        res
+    }
+
+    // height of an empty subtree is 0, of a single leaf is 1
+    fn height_of(node: *mut Self) -> usize {
+        if node.is_null() {
+            0
+        } else {
+            unsafe { (*node).height }
+        }
+    }
+
+    fn balance_factor(&self) -> isize {
+        Self::height_of(self.left) as isize - Self::height_of(self.right) as isize
+    }
+
+    // recomputes height and subtree maximum from the children's
+    fn update(&mut self) {
+        self.height = 1 + max(Self::height_of(self.left), Self::height_of(self.right));
+        self.max = self.expected_max().clone();
+    }
+
+    fn leftmost(&self) -> &Self {
+        let mut node = self;
+        while !node.left.is_null() {
+            node = unsafe { &*node.left };
+        }
+        node
+    }
+
+    // next interval in (low, high) order: the leftmost one on the right, or else
+    // the first ancestor this node hangs left of
+    fn next_node(&self) -> *mut Self {
+        unsafe {
+            if !self.right.is_null() {
+                return (*self.right).leftmost() as *const Self as *mut Self;
+            }
+            let mut node_ptr = self as *const Self as *mut Self;
+            let mut parent_ptr = self.parent;
+            while !parent_ptr.is_null() && (*parent_ptr).right == node_ptr {
+                node_ptr = parent_ptr;
+                parent_ptr = (*parent_ptr).parent;
+            }
+            parent_ptr
+        }
+    }
+}
+
+impl<T> Node<T>
+where
+    T: fmt::Display
+{
+    fn print_traverse(&self, string: &mut String, indent: usize) {
+        let mut stack = vec![(self as *const Self, indent)];
+        while let Some((node_ptr, indent)) = stack.pop() {
+            let node = unsafe { &*node_ptr };
+            string.push_str(&format!("{}[{}, {}] max {}\n", " ".repeat(indent*2), node.low, node.high, node.max));
+            for child in [node.right, node.left] {
+                if !child.is_null() {
+                    stack.push((child, indent+1));
+                }
+            }
+        }
+    }
+}
+
+// an AVL tree of closed intervals; the subtree maxima let overlap queries
+// skip every subtree that ends before the range being asked about, and
+// rotations keep them up to date along with the heights
+pub struct IntervalTree<T>
+where
+    T: Ord + Clone
+{
+    root: *mut Node<T>,
+    num_elements: usize,
+}
+
+impl<T> IntervalTree<T>
+where
+    T: Ord + Clone
+{
+    pub fn new() -> Self {
+        IntervalTree {
+            root: null_mut(),
+            num_elements: 0,
+        }
+    }
+
+    // the same interval may be inserted several times
+    pub fn insert(&mut self, low: T, high: T) -> Result<(), String> {
+        if low > high {
+            return Err("interval ends before it starts".to_string());
+        }
+        let new_ptr = Node::new_ptr(low, high);
+        if self.root.is_null() {
+            self.root = new_ptr;
+        } else {
+            unsafe {
+                let new = &*new_ptr;
+                let mut node_ptr = self.root;
+                loop {
+                    if (*node_ptr).cmp_interval(&new.low, &new.high) == Ordering::Greater {
+                        if (*node_ptr).left.is_null() {
+                            (*node_ptr).left = new_ptr;
+                            break;
+                        }
+                        node_ptr = (*node_ptr).left;
+                    } else {
+                        if (*node_ptr).right.is_null() {
+                            (*node_ptr).right = new_ptr;
+                            break;
+                        }
+                        node_ptr = (*node_ptr).right;
+                    }
+                }
+                (*new_ptr).parent = node_ptr;
+                self.rebalance_from(node_ptr);
+            }
+        }
+        self.num_elements += 1;
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn contains(&self, low: &T, high: &T) -> bool {
+        !self.find(low, high).is_null()
+    }
+
+    // removes one copy of the interval
+    pub fn remove(&mut self, low: &T, high: &T) -> Result<(T, T), String> {
+        if self.root.is_null() {
+            assert_eq!(self.num_elements, 0);
+            return Err("tree is already empty".to_string());
+        }
+        let node_ptr = self.find(low, high);
+        if node_ptr.is_null() {
+            return Err("nothing to delete".to_string());
+        }
+        unsafe {
+            self.unlink(node_ptr);
+            self.num_elements -= 1;
+            Ok(Node::into_interval(node_ptr))
+        }
+    }
+
+    // every interval containing `point`, sorted
+    pub fn overlapping(&self, point: &T) -> Vec<(&T, &T)> {
+        self.overlapping_range(point, point)
+    }
+
+    // every interval sharing at least one point with [low, high], sorted
+    pub fn overlapping_range(&self, low: &T, high: &T) -> Vec<(&T, &T)> {
+        let mut res = Vec::new();
+        if low > high {
+            return res;
+        }
+        let mut stack = Vec::new();
+        let mut node_ptr = self.root;
+        unsafe {
+            loop {
+                // subtrees ending before `low` hold nothing of interest
+                while !node_ptr.is_null() && (*node_ptr).max >= *low {
+                    stack.push(node_ptr);
+                    node_ptr = (*node_ptr).left;
+                }
+                let Some(top) = stack.pop() else {
+                    break;
+                };
+                let node = &*top;
+                // everything after this node in order starts past `high` as well
+                if node.low > *high {
+                    break;
+                }
+                if node.high >= *low {
+                    res.push((&node.low, &node.high));
+                }
+                node_ptr = node.right;
+            }
+        }
+        res
+    }
+
+    // the first interval in sorted order that overlaps [low, high], if any; a
+    // single walk down suffices, since when the left subtree reaches `low` but
+    // holds no overlap, all of it starts past `high` and so does the right one
+    pub fn any_overlap(&self, low: &T, high: &T) -> Option<(&T, &T)> {
+        if low > high {
+            return None;
+        }
+        let mut found = None;
+        let mut node_ptr = self.root;
+        unsafe {
+            while !node_ptr.is_null() {
+                let node = &*node_ptr;
+                if node.max < *low {
+                    break;
+                }
+                let left = node.left;
+                if !left.is_null() && (*left).max >= *low {
+                    // an overlap on the left comes first; otherwise this node is the answer if it overlaps
+                    if node.overlaps(low, high) {
+                        found = Some((&node.low, &node.high));
+                    }
+                    node_ptr = left;
+                } else {
+                    if node.overlaps(low, high) {
+                        return Some((&node.low, &node.high));
+                    }
+                    if node.low > *high {
+                        break;
+                    }
+                    node_ptr = node.right;
+                }
+            }
+        }
+        found
+    }
+
+    #[allow(dead_code)]
+    pub fn min(&self) -> Option<(&T, &T)> {
+        if self.root.is_null() {
+            None
+        } else {
+            unsafe {
+                let node = (*self.root).leftmost();
+                Some((&node.low, &node.high))
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn count(&self) -> usize {
+        self.num_elements
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.num_elements == 0
+    }
+
+    // levels in the tree, 0 when empty
+    #[allow(dead_code)]
+    pub fn height(&self) -> usize {
+        Node::height_of(self.root)
+    }
+
+    // checks interval order, parent links, subtree maxima, heights and balance
+    // factors and the element counter
+    pub fn validate(&self) -> Result<(), IntervalViolation<&T>> {
+        let mut actual = 0;
+        unsafe {
+            if !self.root.is_null() && !(*self.root).parent.is_null() {
+                return Err(IntervalViolation::ParentLink { node: &(*self.root).low });
+            }
+            // each node travels with the nearest ancestors it has to sort after and before
+            let mut stack: Vec<(*mut Node<T>, *const Node<T>, *const Node<T>)> = vec![(self.root, null_mut(), null_mut())];
+            while let Some((node_ptr, low, high)) = stack.pop() {
+                if node_ptr.is_null() {
+                    continue;
+                }
+                let node = &*node_ptr;
+                actual += 1;
+                if node.low > node.high {
+                    return Err(IntervalViolation::Order { node: &node.low, bound: &node.high });
+                }
+                if !low.is_null() && (*low).cmp_interval(&node.low, &node.high) == Ordering::Greater {
+                    return Err(IntervalViolation::Order { node: &node.low, bound: &(*low).low });
+                }
+                if !high.is_null() && (*high).cmp_interval(&node.low, &node.high) == Ordering::Less {
+                    return Err(IntervalViolation::Order { node: &node.low, bound: &(*high).low });
+                }
+                if node.max != *node.expected_max() {
+                    return Err(IntervalViolation::SubtreeMax { node: &node.low });
+                }
+                // every recorded height agreeing with its children's makes them all right
+                let height = 1 + max(Node::height_of(node.left), Node::height_of(node.right));
+                if node.height != height {
+                    return Err(IntervalViolation::Height { node: &node.low, recorded: node.height, actual: height });
+                }
+                let balance = node.balance_factor();
+                if balance.abs() > 1 {
+                    return Err(IntervalViolation::Balance { node: &node.low, balance });
+                }
+                for child in [node.left, node.right] {
+                    if !child.is_null() && (*child).parent != node_ptr {
+                        return Err(IntervalViolation::ParentLink { node: &(*child).low });
+                    }
+                }
+                stack.push((node.left, low, node_ptr));
+                stack.push((node.right, node_ptr, high));
+            }
+        }
+        if actual != self.num_elements {
+            return Err(IntervalViolation::Count { recorded: self.num_elements, actual });
+        }
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn iter(&self) -> Iter<'_, T> {
+        let node = if self.root.is_null() {
+            null_mut()
+        } else {
+            unsafe { (*self.root).leftmost() as *const Node<T> as *mut Node<T> }
+        };
+        Iter {
+            node,
+            tree: PhantomData,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn infix_traverse(&self) -> Vec<(&T, &T)> {
+        self.iter().collect()
+    }
+
+    // the first node holding exactly [low, high], or null
+    fn find(&self, low: &T, high: &T) -> *mut Node<T> {
+        let mut node_ptr = self.root;
+        unsafe {
+            while !node_ptr.is_null() {
+                match (*node_ptr).cmp_interval(low, high) {
+                    Ordering::Less => node_ptr = (*node_ptr).right,
+                    Ordering::Equal => break,
+                    Ordering::Greater => node_ptr = (*node_ptr).left,
+                }
+            }
+        }
+        node_ptr
+    }
+
+    // hangs `new` where `old` was, under `parent` or at the root when `parent` is null
+    unsafe fn replace_child(&mut self, parent: *mut Node<T>, old: *mut Node<T>, new: *mut Node<T>) {
+        if parent.is_null() {
+            self.root = new;
+        } else if (*parent).left == old {
+            (*parent).left = new;
+        } else {
+            (*parent).right = new;
+        }
+    }
+
+    // puts the subtree `new` where `old` hangs
+    unsafe fn transplant(&mut self, old: *mut Node<T>, new: *mut Node<T>) {
+        self.replace_child((*old).parent, old, new);
+        if !new.is_null() {
+            (*new).parent = (*old).parent;
+        }
+    }
+
+    // detaches `node_ptr` from the tree without freeing it; a node with two
+    // children is replaced by its successor, so no interval changes nodes
+    unsafe fn unlink(&mut self, node_ptr: *mut Node<T>) {
+        let node = &*node_ptr;
+        let fix_from;
+        if node.left.is_null() {
+            fix_from = node.parent;
+            self.transplant(node_ptr, node.right);
+        } else if node.right.is_null() {
+            fix_from = node.parent;
+            self.transplant(node_ptr, node.left);
+        } else {
+            let next_ptr = (*node.right).leftmost() as *const Node<T> as *mut Node<T>;
+            if (*next_ptr).parent == node_ptr {
+                fix_from = next_ptr;
+            } else {
+                fix_from = (*next_ptr).parent;
+                self.transplant(next_ptr, (*next_ptr).right);
+                (*next_ptr).right = node.right;
+                (*node.right).parent = next_ptr;
+            }
+            self.transplant(node_ptr, next_ptr);
+            (*next_ptr).left = node.left;
+            (*node.left).parent = next_ptr;
+        }
+        self.rebalance_from(fix_from);
+    }
+
+    //     x              y
+    //    / \            / \
+    //   a   y    =>    x   c
+    //      / \        / \
+    //     b   c      a   b
+    unsafe fn rotate_left(&mut self, x: *mut Node<T>) -> *mut Node<T> {
+        let y = (*x).right;
+        (*x).right = (*y).left;
+        if !(*y).left.is_null() {
+            (*(*y).left).parent = x;
+        }
+        (*y).parent = (*x).parent;
+        self.replace_child((*x).parent, x, y);
+        (*y).left = x;
+        (*x).parent = y;
+        (*x).update();
+        (*y).update();
+        y
+    }
+
+    unsafe fn rotate_right(&mut self, x: *mut Node<T>) -> *mut Node<T> {
+        let y = (*x).left;
+        (*x).left = (*y).right;
+        if !(*y).right.is_null() {
+            (*(*y).right).parent = x;
+        }
+        (*y).parent = (*x).parent;
+        self.replace_child((*x).parent, x, y);
+        (*y).right = x;
+        (*x).parent = y;
+        (*x).update();
+        (*y).update();
+        y
+    }
+
+    // walks up to the root restoring heights, subtree maxima and the AVL balance condition
+    unsafe fn rebalance_from(&mut self, node: *mut Node<T>) {
+        let mut node_ptr = node;
+        while !node_ptr.is_null() {
+            (*node_ptr).update();
+            let balance = (*node_ptr).balance_factor();
+            if balance > 1 {
+                if (*(*node_ptr).left).balance_factor() < 0 {
+                    self.rotate_left((*node_ptr).left);
+                }
+                node_ptr = self.rotate_right(node_ptr);
+            } else if balance < -1 {
+                if (*(*node_ptr).right).balance_factor() > 0 {
+                    self.rotate_right((*node_ptr).right);
+                }
+                node_ptr = self.rotate_left(node_ptr);
+            }
+            node_ptr = (*node_ptr).parent;
+        }
+    }
+}
+
+pub struct Iter<'a, T> {
+    node: *mut Node<T>,
+    tree: PhantomData<&'a Node<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T>
+where
+    T: Ord + Clone
+{
+    type Item = (&'a T, &'a T);
+
+    fn next(&mut self) -> Option<(&'a T, &'a T)> {
+        if self.node.is_null() {
+            return None;
+        }
+        unsafe {
+            let node = &*self.node;
+            self.node = node.next_node();
+            Some((&node.low, &node.high))
+        }
+    }
+}
+
+// frees the nodes without recursing: the top node's left child keeps being
+// rotated over it, and only a node without a left child gets freed
+impl<T> Drop for IntervalTree<T>
+where
+    T: Ord + Clone
+{
+    fn drop(&mut self) {
+        let mut node_ptr = self.root;
+        unsafe {
+            while !node_ptr.is_null() {
+                let left = (*node_ptr).left;
+                if left.is_null() {
+                    let right = (*node_ptr).right;
+                    Node::into_interval(node_ptr);
+                    node_ptr = right;
+                } else {
+                    (*node_ptr).left = (*left).right;
+                    (*left).right = node_ptr;
+                    node_ptr = left;
+                }
+            }
+        }
+    }
+}
+
+impl<T> fmt::Display for IntervalTree<T>
+where
+    T: Ord + Clone + fmt::Display
+{
+    fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.num_elements == 0 {
+            writeln!(f, "empty tree")
+        } else {
+            unsafe {
+                let mut out = String::new();
+                (*self.root).print_traverse(&mut out, 0);
+                write!(f, "{}", &out[..out.len()-1])
+            }
+        }
+    }
+}
+
+// the first broken invariant `IntervalTree::validate` comes across; nodes are
+// named by their low endpoint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntervalViolation<T> {
+    // `node` is out of order with the interval starting at `bound`, or ends before it starts
+    Order { node: T, bound: T },
+    // `node` names a parent that does not hold it as a child
+    ParentLink { node: T },
+    // the subtree maximum recorded at `node` is not the largest endpoint below it
+    SubtreeMax { node: T },
+    Height { node: T, recorded: usize, actual: usize },
+    Balance { node: T, balance: isize },
+    Count { recorded: usize, actual: usize },
+}
+
+impl<T> fmt::Display for IntervalViolation<T>
+where
+    T: fmt::Display
+{
+    fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntervalViolation::Order { node, bound } => write!(f, "interval at {} is out of order with {}", node, bound),
+            IntervalViolation::ParentLink { node } => write!(f, "interval at {} has a broken parent link", node),
+            IntervalViolation::SubtreeMax { node } => write!(f, "interval at {} records the wrong subtree maximum", node),
+            IntervalViolation::Height { node, recorded, actual } => write!(f, "interval at {} records height {} but has {}", node, recorded, actual),
+            IntervalViolation::Balance { node, balance } => write!(f, "interval at {} has balance factor {}", node, balance),
+            IntervalViolation::Count { recorded, actual } => write!(f, "tree records {} intervals but holds {}", recorded, actual),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    // intervals arriving sorted by start still give a tree of logarithmic height
+    #[test]
+    fn sorted_inserts_stay_balanced() {
+        let n = 100_000;
+        let mut tree = IntervalTree::new();
+        for i in 0..n {
+            tree.insert(i, i + 10).unwrap();
+        }
+        tree.validate().unwrap();
+        assert!(tree.height() <= 25);
+        assert_eq!(tree.count(), n as usize);
+        assert_eq!(tree.overlapping(&50_000).len(), 11);
+        for i in (0..n).step_by(2) {
+            assert_eq!(tree.remove(&i, &(i + 10)), Ok((i, i + 10)));
+        }
+        tree.validate().unwrap();
+        assert!(tree.height() <= 25);
+        assert_eq!(tree.overlapping(&50_000).len(), 5);
+        assert_eq!(tree.to_string().lines().count(), tree.count());
+    }
+
+    #[test]
+    fn queries() {
+        let mut meetings = IntervalTree::new();
+        for (start, end) in [(540, 600), (570, 630), (720, 780), (600, 615), (900, 960), (555, 560)] {
+            meetings.insert(start, end).unwrap();
+        }
+        assert!(meetings.insert(700, 650).is_err());
+        assert!(meetings.contains(&540, &600));
+        assert!(!meetings.contains(&540, &601));
+        assert_eq!(meetings.min(), Some((&540, &600)));
+        assert_eq!(meetings.any_overlap(&500, &545), Some((&540, &600)));
+        assert_eq!(meetings.any_overlap(&800, &850), None);
+        assert_eq!(meetings.iter().filter(|(start, _)| **start >= 600).count(), 3);
+        meetings.remove(&570, &630).unwrap();
+        assert_eq!(meetings.overlapping(&600), [(&540, &600), (&600, &615)]);
+        assert_eq!(meetings.infix_traverse().len(), 5);
+        assert!(!meetings.is_empty());
+        meetings.validate().unwrap();
+    }
+
+    // endpoints only need an order, not a printed form
+    #[test]
+    fn holds_durations() {
+        let mut tree = IntervalTree::new();
+        tree.insert(Duration::from_secs(1), Duration::from_secs(5)).unwrap();
+        tree.insert(Duration::from_secs(3), Duration::from_secs(4)).unwrap();
+        assert_eq!(tree.overlapping(&Duration::from_secs(2)).len(), 1);
+        tree.validate().unwrap();
+    }
+}
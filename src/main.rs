@@ -1,5 +1,43 @@
 mod bst;
+mod avl_tree;
+mod red_black_tree;
+mod bst_map;
+mod queue;
+mod ring_queue;
+mod deque;
+mod blocking_queue;
+mod mpsc_queue;
+mod priority_queue;
+mod splay_tree;
+mod treap;
+mod btree;
+mod interval_tree;
+mod persistent_bst;
 use bst::BinarySearchTree;
+use avl_tree::AvlTree;
+use red_black_tree::RedBlackTree;
+use bst_map::BstMap;
+use queue::Queue;
+use ring_queue::RingQueue;
+use deque::Deque;
+use blocking_queue::BlockingQueue;
+use mpsc_queue::MpscQueue;
+use priority_queue::{ PriorityQueue, HeapOrder };
+use splay_tree::SplayTree;
+use treap::Treap;
+use btree::BTree;
+use interval_tree::IntervalTree;
+use persistent_bst::{ PersistentTree, VersionedTree };
+use rand::Rng;
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+#[derive(Debug)]
+struct Person {
+    name: String,
+    age: u32,
+}
 
 fn main() {
     let mut tree: BinarySearchTree<i32> = BinarySearchTree::new();
@@ -20,18 +58,445 @@ fn main() {
 
     dbg!(tree.min());
     dbg!(tree.max());
+    dbg!(tree.floor(&45));
+    dbg!(tree.ceiling(&45));
+    dbg!(tree.predecessor(&42));
+    dbg!(tree.successor(&42));
+    dbg!(tree.range(40..55).collect::<Vec<_>>());
+    dbg!(tree.rank(&50));
+    dbg!(tree.select(3));
+    dbg!(tree.median());
+    dbg!(tree.iter().take(3).collect::<Vec<_>>());
+    dbg!(tree.iter().rev().take(3).collect::<Vec<_>>());
+    dbg!(tree.iter_level_order().collect::<Vec<_>>());
+    dbg!(tree.levels());
+    dbg!(tree.height());
+    dbg!(tree.depth_of(&41));
+    dbg!(tree.lca(&41, &15));
+    dbg!(tree.lca(&52, &777));
+    dbg!(tree.lca(&7, &100));
+    dbg!(tree.path_to(&41));
+    dbg!(tree.distance(&41, &52));
+    dbg!(tree.is_ancestor(&39, &41));
+    dbg!(tree.is_ancestor(&41, &39));
+
+    // the same values inserted in another order make an equal tree of a different shape
+    let snapshot = tree.clone();
+    let mut reordered: BinarySearchTree<i32> = BinarySearchTree::new();
+    for value in [50, 7, 777, 41, 15, 52, 40, 55, 39, 42] {
+        reordered.insert(value);
+    }
+    dbg!(&reordered);
+    dbg!(snapshot == tree);
+    dbg!(snapshot.same_shape(&tree));
+    dbg!(reordered == tree);
+    dbg!(reordered.same_shape(&tree));
+    reordered.remove(&777).unwrap();
+    reordered.insert(100);
+    dbg!(tree.diff(&reordered));
+    tree.validate().unwrap();
+    println!("{}", tree.to_ascii_tree());
+    println!("{}", tree.to_dot());
     println!("{}", tree);
-    tree.remove(42);
-    tree.remove(39);
-    tree.remove(50);
-    tree.remove(52);
-    tree.remove(55);
-    tree.remove(777);
-    tree.remove(42);
-    tree.remove(40).unwrap();
-    tree.remove(41);
-    tree.remove(15);
-    tree.remove(7);
+    let _ = tree.remove(&42);
+    let _ = tree.remove(&39);
+    let _ = tree.remove(&50);
+    let _ = tree.remove(&52);
+    let _ = tree.remove(&55);
+    let _ = tree.remove(&777);
+    let _ = tree.remove(&42);
+    tree.remove(&40).unwrap();
+    let _ = tree.remove(&41);
+    let _ = tree.remove(&15);
+    let _ = tree.remove(&7);
     println!("=================");
     println!("{}", tree);
+
+    let values: Vec<i32> = (1..=15).collect();
+    let mut balanced = BinarySearchTree::from_sorted(&values).unwrap();
+    println!("{}", balanced.to_ascii_tree());
+    let mut upper = balanced.split_off(&9);
+    dbg!(balanced.infix_traverse());
+    dbg!(upper.infix_traverse());
+    let mut extra = BinarySearchTree::from_sorted(&[4, 10, 20]).unwrap();
+    dbg!(BinarySearchTree::from_sorted(&[3, 1, 2]).unwrap_err());
+    let scores = BinarySearchTree::multiset_from_sorted(&[60, 75, 75, 75, 90]).unwrap();
+    dbg!(scores.count_of(&75));
+    upper.append(&mut extra);
+    dbg!(upper.infix_traverse());
+    let joined = balanced.join(upper);
+    dbg!(joined.count());
+    println!("{}", joined.to_ascii_tree());
+
+    let mut letters: BinarySearchTree<char> = BinarySearchTree::new_multiset();
+    for c in "mississippi".chars() {
+        letters.insert(c);
+    }
+    dbg!(letters.count());
+    dbg!(letters.count_of(&'s'));
+    dbg!(letters.infix_traverse());
+    dbg!(letters.infix_traverse_distinct());
+    letters.remove_one(&'p').unwrap();
+    dbg!(letters.remove_all(&'s'));
+    dbg!(letters.select(2));
+    letters.validate().unwrap();
+    println!("{}", letters);
+
+    let mut people = BinarySearchTree::with_comparator(|a: &Person, b: &Person| a.age.cmp(&b.age));
+    for (name, age) in [("ada", 36), ("alan", 41), ("grace", 85), ("linus", 21), ("barbara", 65)] {
+        people.insert(Person { name: name.to_string(), age });
+    }
+    dbg!(&people.min().unwrap().name);
+    dbg!(&people.ceiling(&Person { name: String::new(), age: 40 }).unwrap().name);
+    dbg!(people.remove(&Person { name: String::new(), age: 85 }).unwrap().name);
+    people.validate().unwrap();
+    dbg!(people.iter().map(|person| &person.name).collect::<Vec<_>>());
+
+    let mut descending = BinarySearchTree::with_comparator(|a: &i32, b: &i32| b.cmp(a));
+    for i in [42, 39, 55, 40, 777, 50] {
+        descending.insert(i);
+    }
+    dbg!(descending.infix_traverse());
+    dbg!(descending.range(..=42).collect::<Vec<_>>());
+
+    let mut names = BinarySearchTree::multiset_with_comparator(|a: &&str, b: &&str| {
+        a.to_lowercase().cmp(&b.to_lowercase())
+    });
+    for name in ["Bob", "alice", "BOB", "Carol", "bob"] {
+        names.insert(name);
+    }
+    dbg!(names.count_of(&"bob"));
+    dbg!(names.infix_traverse());
+    // the comparator decides equality, so the case of a repeat does not matter
+    let mut shouted = names.clone();
+    shouted.remove(&"bob").unwrap();
+    shouted.insert("BOB");
+    dbg!(shouted == names);
+    dbg!(shouted.same_shape(&names));
+
+    let mut avl: AvlTree<i32> = AvlTree::new();
+    for i in 1..=15 {
+        avl.insert(i);
+    }
+    dbg!(avl.count());
+    dbg!(avl.height());
+    avl.validate().unwrap();
+    dbg!(avl.prefix_traverse());
+    avl.remove(&8).unwrap();
+    avl.remove(&1).unwrap();
+    avl.remove(&2).unwrap();
+    dbg!(avl.infix_traverse());
+    dbg!(avl.min());
+    dbg!(avl.max());
+    println!("{}", avl);
+
+    let mut rb: RedBlackTree<i32> = RedBlackTree::new();
+    for i in [42, 39, 55, 40, 777, 50, 15, 41, 7, 52] {
+        rb.insert(i);
+    }
+    dbg!(rb.count());
+    dbg!(rb.infix_traverse());
+    println!("{}", rb);
+    rb.remove(&42).unwrap();
+    rb.remove(&7).unwrap();
+    dbg!(rb.min());
+    dbg!(rb.max());
+    rb.validate().unwrap();
+    println!("{}", rb);
+
+    let mut splay: SplayTree<i32> = SplayTree::new();
+    for i in [42, 39, 55, 40, 777, 50, 15, 41, 7, 52] {
+        splay.insert(i);
+    }
+    dbg!(splay.root());
+    dbg!(splay.contents(&40));
+    dbg!(splay.root());
+    dbg!(splay.remove(&42).unwrap());
+    dbg!(splay.infix_traverse());
+    splay.validate().unwrap();
+    println!("{}", splay);
+
+    // the same seed always builds the same treap
+    let mut treap: Treap<i32> = Treap::with_seed(42);
+    for i in 1..=15 {
+        treap.insert(i);
+    }
+    dbg!(treap.height());
+    dbg!(treap.contains(&7));
+    dbg!(treap.remove(&7).unwrap());
+    dbg!(treap.prefix_traverse());
+    treap.validate().unwrap();
+    println!("{}", treap);
+    let (low, high) = treap.split(&9);
+    dbg!(low.infix_traverse());
+    dbg!(high.infix_traverse());
+    let treap = Treap::merge(low, high).unwrap();
+    dbg!(treap.count());
+    treap.validate().unwrap();
+
+    let mut btree: BTree<i32, String> = BTree::with_min_degree(2);
+    for i in [50, 20, 80, 10, 30, 60, 90, 40, 70, 25, 35, 65] {
+        btree.insert(i, format!("v{}", i));
+    }
+    println!("{}", btree);
+    dbg!(btree.height());
+    dbg!(btree.get(&30));
+    dbg!(btree.insert(30, "thirty".to_string()));
+    dbg!(btree.remove(&50));
+    dbg!(btree.remove(&51));
+    dbg!(btree.range(20..=60).map(|(k, _)| *k).collect::<Vec<_>>());
+    dbg!(btree.min());
+    btree.validate().unwrap();
+    println!("{}", btree);
+
+    // a wide node keeps the tree shallow, so lookups touch far fewer nodes
+    // than an unbalanced binary tree fed the same random keys
+    let n = 200_000;
+    let mut rng = rand::thread_rng();
+    let keys: Vec<u64> = (0..n).map(|_| rng.gen()).collect();
+    let start = Instant::now();
+    let mut bst: BinarySearchTree<u64> = BinarySearchTree::new();
+    for key in &keys {
+        bst.insert(*key);
+    }
+    let bst_insert = start.elapsed();
+    let start = Instant::now();
+    let found = keys.iter().filter(|key| bst.contents(key)).count();
+    let bst_lookup = start.elapsed();
+    println!("bst:   insert {:?}, lookup {:?}, height {}, found {}", bst_insert, bst_lookup, bst.height(), found);
+    let start = Instant::now();
+    let mut btree: BTree<u64, ()> = BTree::new();
+    for key in &keys {
+        btree.insert(*key, ());
+    }
+    let btree_insert = start.elapsed();
+    let start = Instant::now();
+    let found = keys.iter().filter(|key| btree.contains_key(key)).count();
+    let btree_lookup = start.elapsed();
+    println!("btree: insert {:?}, lookup {:?}, height {}, found {}", btree_insert, btree_lookup, btree.height(), found);
+
+    // meeting slots in minutes since midnight
+    let mut meetings: IntervalTree<u32> = IntervalTree::new();
+    for (start, end) in [(540, 600), (570, 630), (720, 780), (600, 615), (900, 960), (555, 560)] {
+        meetings.insert(start, end).unwrap();
+    }
+    println!("{}", meetings);
+    dbg!(meetings.overlapping(&600));
+    dbg!(meetings.overlapping_range(&610, &730));
+    dbg!(meetings.any_overlap(&800, &850));
+    dbg!(meetings.any_overlap(&500, &545));
+    dbg!(meetings.insert(700, 650).unwrap_err());
+    dbg!(meetings.remove(&570, &630).unwrap());
+    dbg!(meetings.overlapping(&600));
+    meetings.validate().unwrap();
+
+    // every update is a new version; the old ones stay as they were
+    let empty: PersistentTree<i32> = PersistentTree::new();
+    let small = empty.insert(20).insert(10).insert(30);
+    let large = small.insert(25).insert(40);
+    dbg!(small.infix_traverse());
+    dbg!(large.infix_traverse());
+    dbg!(large.remove(&20).unwrap().prefix_traverse());
+    dbg!(large.contents(&20));
+    dbg!(large.insert(30).ptr_eq(&large));
+    large.validate().unwrap();
+    println!("{}", large);
+
+    let mut history: VersionedTree<&str> = VersionedTree::new();
+    for word in ["pear", "apple", "fig", "kiwi"] {
+        history.insert(word);
+    }
+    let before_removal = history.latest();
+    history.remove(&"apple").unwrap();
+    history.insert("plum");
+    dbg!(history.current().infix_traverse());
+    dbg!(history.at(before_removal).unwrap().infix_traverse());
+    dbg!(history.contents_at(2, &"fig"));
+    dbg!(history.contents_at(3, &"fig"));
+    dbg!(history.revert(before_removal).unwrap());
+    dbg!(history.current().infix_traverse());
+
+    let mut ring: RingQueue<i32> = RingQueue::with_capacity(2);
+    for i in 1..=5 {
+        ring.push(i);
+    }
+    dbg!(ring.capacity());
+    dbg!(ring.pop());
+    dbg!(ring.peek());
+    ring.push(6);
+    ring.shrink_to_fit();
+    dbg!(ring.len());
+    dbg!(ring.capacity());
+
+    // one allocation per push for the linked queue, a handful of reallocations
+    // for the ring buffer; messages go through in bursts, as they would between threads
+    let n = 1_000_000;
+    let burst = 1000;
+    let start = Instant::now();
+    let mut linked: Queue<u64> = Queue::new();
+    let mut sum = 0;
+    for i in 0..n / burst {
+        for j in 0..burst {
+            linked.push(i * burst + j);
+        }
+        while let Some(value) = linked.pop() {
+            sum += value;
+        }
+    }
+    println!("linked queue: {:?}, sum {}", start.elapsed(), sum);
+    let start = Instant::now();
+    let mut ring: RingQueue<u64> = RingQueue::new();
+    let mut sum = 0;
+    for i in 0..n / burst {
+        for j in 0..burst {
+            ring.push(i * burst + j);
+        }
+        while let Some(value) = ring.pop() {
+            sum += value;
+        }
+    }
+    println!("ring queue:   {:?}, sum {}, capacity {}", start.elapsed(), sum, ring.capacity());
+
+    let mut deque: Deque<i32> = Deque::new();
+    for i in 1..=3 {
+        deque.push_back(i);
+        deque.push_front(-i);
+    }
+    dbg!(deque.iter().collect::<Vec<_>>());
+    deque.rotate_left(2);
+    dbg!(deque.iter().collect::<Vec<_>>());
+    deque.rotate_right(3);
+    dbg!(deque.iter().rev().collect::<Vec<_>>());
+    dbg!(deque.get(4));
+    dbg!(deque.pop_front());
+    dbg!(deque.pop_back());
+    dbg!(deque.front());
+    dbg!(deque.back());
+
+    // maximum of every window of 3 readings: the deque holds the indices of
+    // readings that can still become a window's maximum, largest at the front
+    let readings = [4, 2, 12, 11, -5, 7, 3, 8, 1];
+    let window = 3;
+    let mut candidates: Deque<usize> = Deque::new();
+    let mut maxima = Vec::new();
+    for (i, reading) in readings.iter().enumerate() {
+        while candidates.back().is_some_and(|&j| readings[j] <= *reading) {
+            candidates.pop_back();
+        }
+        candidates.push_back(i);
+        if candidates.front().is_some_and(|&j| j + window <= i) {
+            candidates.pop_front();
+        }
+        if i + 1 >= window {
+            maxima.push(readings[*candidates.front().unwrap()]);
+        }
+    }
+    dbg!(maxima);
+
+    let mailbox: BlockingQueue<String> = BlockingQueue::new(2);
+    dbg!(mailbox.try_push("first".to_string()).is_ok());
+    dbg!(mailbox.try_push("second".to_string()).is_ok());
+    dbg!(mailbox.try_push("third".to_string()).unwrap_err());
+    dbg!(mailbox.try_pop());
+    mailbox.close();
+    dbg!(mailbox.push("late".to_string()).unwrap_err());
+    dbg!(mailbox.pop());
+    dbg!(mailbox.pop());
+
+    // log lines from several threads through the lock-free queue and through
+    // the mutex-guarded one
+    let threads = 4;
+    let per_thread = 100_000;
+    let start = Instant::now();
+    let mut log: MpscQueue<(usize, usize)> = MpscQueue::new();
+    let handles: Vec<_> = (0..threads).map(|t| {
+        let producer = log.producer();
+        thread::spawn(move || {
+            for line in 0..per_thread {
+                producer.push((t, line));
+            }
+        })
+    }).collect();
+    let mut received = 0;
+    while received < threads * per_thread {
+        match log.pop() {
+            Some(_) => received += 1,
+            None => std::hint::spin_loop(),
+        }
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    println!("lock-free queue: {} lines in {:?}", received, start.elapsed());
+    let start = Instant::now();
+    let log = Arc::new(BlockingQueue::new(threads * per_thread));
+    let handles: Vec<_> = (0..threads).map(|t| {
+        let log = Arc::clone(&log);
+        thread::spawn(move || {
+            for line in 0..per_thread {
+                log.push((t, line)).unwrap();
+            }
+        })
+    }).collect();
+    for _ in 0..threads * per_thread {
+        log.pop().unwrap();
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    println!("mutex queue:     {} lines in {:?}", threads * per_thread, start.elapsed());
+
+    let mut tasks: PriorityQueue<u32> = PriorityQueue::from_vec(vec![3, 9, 1, 7, 5], HeapOrder::Max);
+    dbg!(tasks.peek());
+    let urgent = tasks.push(4);
+    dbg!(tasks.change_priority(urgent, 10).unwrap());
+    dbg!(tasks.pop());
+    dbg!(tasks.get(urgent));
+    dbg!(tasks.len());
+    dbg!(tasks.into_sorted_vec());
+    let (mut smallest, handles) = PriorityQueue::from_vec_with_handles(vec![3, 9, 1, 7, 5], HeapOrder::Min);
+    dbg!(smallest.peek());
+    dbg!(smallest.change_priority(handles[1], 0).unwrap());
+    dbg!(smallest.peek());
+    dbg!(smallest.get(handles[4]));
+
+    // shortest distances from node 0; a node's queue entry is lowered in place
+    // whenever a shorter way to it turns up, so no stale entries pile up
+    let edges = [(0, 1, 7), (0, 2, 9), (0, 5, 14), (1, 2, 10), (1, 3, 15), (2, 3, 11), (2, 5, 2), (3, 4, 6), (4, 5, 9)];
+    let nodes = 6;
+    let mut neighbours = vec![Vec::new(); nodes];
+    for (a, b, weight) in edges {
+        neighbours[a].push((b, weight));
+        neighbours[b].push((a, weight));
+    }
+    let mut dist = vec![u32::MAX; nodes];
+    let mut handles = vec![None; nodes];
+    let mut frontier = PriorityQueue::new(HeapOrder::Min);
+    dist[0] = 0;
+    handles[0] = Some(frontier.push((0, 0)));
+    while let Some((d, node)) = frontier.pop() {
+        for &(next, weight) in &neighbours[node] {
+            if d + weight < dist[next] {
+                dist[next] = d + weight;
+                match handles[next].filter(|handle| frontier.get(*handle).is_some()) {
+                    Some(handle) => {
+                        frontier.change_priority(handle, (dist[next], next)).unwrap();
+                    },
+                    None => handles[next] = Some(frontier.push((dist[next], next))),
+                }
+            }
+        }
+    }
+    dbg!(dist);
+
+    let mut words: BstMap<String, usize> = BstMap::new();
+    for word in "the quick brown fox jumps over the lazy dog the end".split(' ') {
+        *words.entry(word.to_string()).or_insert_with(|| 0) += 1;
+    }
+    dbg!(words.len());
+    dbg!(words.get(&"the".to_string()));
+    dbg!(words.insert("fox".to_string(), 10));
+    dbg!(words.remove(&"dog".to_string()));
+    dbg!(words.min());
+    println!("{}", words);
 }
@@ -0,0 +1,239 @@
+use std::ptr::null_mut;
+use std::alloc::{ alloc, dealloc, Layout, handle_alloc_error };
+use std::cell::{ Cell, UnsafeCell };
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicPtr, Ordering };
+
+struct Node<T> {
+    // None in the stub node the consumer stands on
+    value: Option<T>,
+    next: AtomicPtr<Self>,
+}
+
+impl<T> Node<T> {
+    fn new_ptr(value: Option<T>) -> *mut Self {
+        let layout = Layout::new::<Node<T>>();
+        unsafe {
+            let node_ptr = alloc(layout) as *mut Node<T>;
+            if node_ptr.is_null() {
+                handle_alloc_error(layout);
+            }
+            node_ptr.write(Node {
+                value,
+                next: AtomicPtr::new(null_mut()),
+            });
+            node_ptr
+        }
+    }
+
+    unsafe fn free(node_ptr: *mut Self) {
+        std::ptr::drop_in_place(node_ptr);
+        dealloc(node_ptr as *mut u8, Layout::new::<Node<T>>());
+    }
+}
+
+// a singly linked list from the oldest node at `tail` to the newest at `head`;
+// the node at `tail` is a stub whose value has already been taken
+struct Shared<T> {
+    // producers swap their node in here, then link the node they replaced to it
+    head: AtomicPtr<Node<T>>,
+    // touched by the consumer only
+    tail: UnsafeCell<*mut Node<T>>,
+}
+
+// producers only go through the atomic `head`, and `tail` is only used
+// behind the single `MpscQueue`, so values are all that crosses threads
+unsafe impl<T: Send> Send for Shared<T> {}
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    fn push(&self, value: T) {
+        let node_ptr = Node::new_ptr(Some(value));
+        let prev_ptr = self.head.swap(node_ptr, Ordering::AcqRel);
+        // until this store the consumer sees the list end at `prev_ptr`, and
+        // `prev_ptr` is not freed before the consumer has moved past it
+        unsafe {
+            (*prev_ptr).next.store(node_ptr, Ordering::Release);
+        }
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        let mut node_ptr = *self.tail.get_mut();
+        while !node_ptr.is_null() {
+            unsafe {
+                let next_ptr = *(*node_ptr).next.get_mut();
+                Node::free(node_ptr);
+                node_ptr = next_ptr;
+            }
+        }
+    }
+}
+
+// the consuming end of a lock-free multi-producer single-consumer FIFO queue
+// (Vyukov's design); a push is one swap and one store and never waits, and
+// `producer` hands out handles that other threads push through
+pub struct MpscQueue<T> {
+    shared: Arc<Shared<T>>,
+    // keeps the consumer from being shared, since `peek` lends out values
+    // that only the consuming thread may look at
+    consumer: PhantomData<Cell<()>>,
+}
+
+impl<T> MpscQueue<T> {
+    pub fn new() -> Self {
+        let stub_ptr = Node::new_ptr(None);
+        MpscQueue {
+            shared: Arc::new(Shared {
+                head: AtomicPtr::new(stub_ptr),
+                tail: UnsafeCell::new(stub_ptr),
+            }),
+            consumer: PhantomData,
+        }
+    }
+
+    pub fn producer(&self) -> Producer<T> {
+        Producer {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn push(&self, value: T) {
+        self.shared.push(value);
+    }
+
+    // None when empty, and also while the oldest push has swapped its node
+    // in but not linked it yet; values come out in the order of their swaps
+    pub fn pop(&mut self) -> Option<T> {
+        unsafe {
+            let tail = &mut *self.shared.tail.get();
+            let next_ptr = (**tail).next.load(Ordering::Acquire);
+            if next_ptr.is_null() {
+                return None;
+            }
+            // `next_ptr` becomes the stub, and the old stub is no producer's `prev_ptr` any more
+            let value = (*next_ptr).value.take();
+            Node::free(*tail);
+            *tail = next_ptr;
+            value
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn peek(&self) -> Option<&T> {
+        unsafe {
+            let next_ptr = (**self.shared.tail.get()).next.load(Ordering::Acquire);
+            if next_ptr.is_null() {
+                None
+            } else {
+                (*next_ptr).value.as_ref()
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.peek().is_none()
+    }
+}
+
+// a handle for pushing from any thread; clone one per producer
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Producer<T> {
+    pub fn push(&self, value: T) {
+        self.shared.push(value);
+    }
+}
+
+impl<T> Clone for Producer<T> {
+    fn clone(&self) -> Self {
+        Producer {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn assert_send<T: Send>() {}
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn handles_cross_threads() {
+        assert_send::<MpscQueue<Cell<i32>>>();
+        assert_send_sync::<Producer<Cell<i32>>>();
+    }
+
+    // every producer's values must arrive in the order pushed, none lost and none twice
+    #[test]
+    fn producers_race() {
+        let producers = 8;
+        let per_producer = 50_000;
+        let mut queue: MpscQueue<(usize, usize)> = MpscQueue::new();
+        let handles: Vec<_> = (0..producers).map(|p| {
+            let producer = queue.producer();
+            thread::spawn(move || {
+                for i in 0..per_producer {
+                    producer.push((p, i));
+                }
+            })
+        }).collect();
+        let mut expected = vec![0; producers];
+        let mut received = 0;
+        while received < producers * per_producer {
+            match queue.pop() {
+                Some((p, i)) => {
+                    assert_eq!(i, expected[p]);
+                    expected[p] += 1;
+                    received += 1;
+                },
+                None => std::hint::spin_loop(),
+            }
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert!(queue.pop().is_none());
+        assert!(queue.is_empty());
+    }
+
+    // the consumer may push as well, in line with the producers
+    #[test]
+    fn consumer_pushes_too() {
+        let mut queue = MpscQueue::new();
+        let producer = queue.producer();
+        assert!(queue.is_empty());
+        producer.push(1);
+        queue.push(2);
+        assert_eq!(queue.peek(), Some(&1));
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), Some(2));
+        assert!(queue.is_empty());
+    }
+
+    // values left behind are dropped with the last handle, whichever end that is
+    #[test]
+    fn drops_leftovers() {
+        let tracker = Arc::new(());
+        let mut queue = MpscQueue::new();
+        let producer = queue.producer();
+        for _ in 0..10 {
+            producer.push(Arc::clone(&tracker));
+        }
+        queue.pop().unwrap();
+        assert_eq!(queue.peek().map(Arc::strong_count), Some(10));
+        drop(queue);
+        producer.push(Arc::clone(&tracker));
+        drop(producer);
+        assert_eq!(Arc::strong_count(&tracker), 1);
+    }
+}
@@ -0,0 +1,464 @@
+use std::rc::Rc;
+use std::cmp::{ Ordering, max };
+use std::fmt;
+use crate::bst::TreeViolation;
+
+type Link<T> = Option<Rc<Node<T>>>;
+
+// nodes are never changed once built; an update copies the nodes on the path
+// it walks and points the copies at the untouched subtrees of the old version
+struct Node<T> {
+    value: T,
+    height: usize,
+    left: Link<T>,
+    right: Link<T>,
+}
+
+impl<T> Node<T>
+where
+    T: Ord + Clone
+{
+    // an empty link counts 0 levels, a childless node 1
+    fn height_of(link: &Link<T>) -> usize {
+        match link {
+            None => 0,
+            Some(node) => node.height,
+        }
+    }
+
+    fn make(value: T, left: Link<T>, right: Link<T>) -> Rc<Self> {
+        Rc::new(Node {
+            height: 1 + max(Self::height_of(&left), Self::height_of(&right)),
+            value,
+            left,
+            right,
+        })
+    }
+
+    // like `make`, but rotates the new node when its subtrees differ in height by two
+    fn balance(value: T, left: Link<T>, right: Link<T>) -> Rc<Self> {
+        let left_height = Self::height_of(&left);
+        let right_height = Self::height_of(&right);
+        if left_height > right_height + 1 {
+            let l = left.unwrap();
+            if Self::height_of(&l.left) >= Self::height_of(&l.right) {
+                Self::make(l.value.clone(), l.left.clone(), Some(Self::make(value, l.right.clone(), right)))
+            } else {
+                let lr = l.right.as_ref().unwrap();
+                Self::make(
+                    lr.value.clone(),
+                    Some(Self::make(l.value.clone(), l.left.clone(), lr.left.clone())),
+                    Some(Self::make(value, lr.right.clone(), right)),
+                )
+            }
+        } else if right_height > left_height + 1 {
+            let r = right.unwrap();
+            if Self::height_of(&r.right) >= Self::height_of(&r.left) {
+                Self::make(r.value.clone(), Some(Self::make(value, left, r.left.clone())), r.right.clone())
+            } else {
+                let rl = r.left.as_ref().unwrap();
+                Self::make(
+                    rl.value.clone(),
+                    Some(Self::make(value, left, rl.left.clone())),
+                    Some(Self::make(r.value.clone(), rl.right.clone(), r.right.clone())),
+                )
+            }
+        } else {
+            Self::make(value, left, right)
+        }
+    }
+
+    // the new subtree, or None if `value` is already there and nothing changes
+    fn insert(link: &Link<T>, value: T) -> Option<Link<T>> {
+        match link {
+            None => Some(Some(Self::make(value, None, None))),
+            Some(node) => match value.cmp(&node.value) {
+                Ordering::Less => Self::insert(&node.left, value)
+                    .map(|left| Some(Self::balance(node.value.clone(), left, node.right.clone()))),
+                Ordering::Equal => None,
+                Ordering::Greater => Self::insert(&node.right, value)
+                    .map(|right| Some(Self::balance(node.value.clone(), node.left.clone(), right))),
+            },
+        }
+    }
+
+    // the new subtree, or None if `value` is not there
+    fn remove(link: &Link<T>, value: &T) -> Option<Link<T>> {
+        let node = link.as_ref()?;
+        match value.cmp(&node.value) {
+            Ordering::Less => Self::remove(&node.left, value)
+                .map(|left| Some(Self::balance(node.value.clone(), left, node.right.clone()))),
+            Ordering::Greater => Self::remove(&node.right, value)
+                .map(|right| Some(Self::balance(node.value.clone(), node.left.clone(), right))),
+            Ordering::Equal => match (&node.left, &node.right) {
+                (None, right) => Some(right.clone()),
+                (left, None) => Some(left.clone()),
+                (left, Some(right)) => {
+                    let (next, right) = Self::remove_min(right);
+                    Some(Some(Self::balance(next, left.clone(), right)))
+                },
+            },
+        }
+    }
+
+    // the smallest value of the subtree and the subtree without it
+    fn remove_min(node: &Rc<Self>) -> (T, Link<T>) {
+        match &node.left {
+            None => (node.value.clone(), node.right.clone()),
+            Some(left) => {
+                let (min, left) = Self::remove_min(left);
+                (min, Some(Self::balance(node.value.clone(), left, node.right.clone())))
+            },
+        }
+    }
+
+    // checks ordering against the bounds and the recorded heights and balance
+    // factors below this node, counting its nodes; returns the actual height
+    fn validate<'a>(&'a self, low: Option<&'a T>, high: Option<&'a T>, count: &mut usize) -> Result<usize, TreeViolation<&'a T>> {
+        *count += 1;
+        if let Some(bound) = low.filter(|bound| self.value <= **bound).or(high.filter(|bound| self.value >= **bound)) {
+            return Err(TreeViolation::Order { node: &self.value, bound });
+        }
+        let left_height = match &self.left {
+            None => 0,
+            Some(left) => left.validate(low, Some(&self.value), count)?,
+        };
+        let right_height = match &self.right {
+            None => 0,
+            Some(right) => right.validate(Some(&self.value), high, count)?,
+        };
+        let actual = 1 + max(left_height, right_height);
+        if self.height != actual {
+            return Err(TreeViolation::Height { node: &self.value, recorded: self.height, actual });
+        }
+        let balance = left_height as isize - right_height as isize;
+        if balance.abs() > 1 {
+            return Err(TreeViolation::Balance { node: &self.value, balance });
+        }
+        Ok(actual)
+    }
+}
+
+impl<T> Node<T> {
+    fn prefix_traverse<'a>(&'a self, vec: &mut Vec<&'a T>) {
+        vec.push(&self.value);
+        if let Some(left) = &self.left {
+            left.prefix_traverse(vec);
+        }
+        if let Some(right) = &self.right {
+            right.prefix_traverse(vec);
+        }
+    }
+
+    fn postfix_traverse<'a>(&'a self, vec: &mut Vec<&'a T>) {
+        if let Some(left) = &self.left {
+            left.postfix_traverse(vec);
+        }
+        if let Some(right) = &self.right {
+            right.postfix_traverse(vec);
+        }
+        vec.push(&self.value);
+    }
+}
+
+impl<T> Node<T>
+where
+    T: fmt::Display
+{
+    fn print_traverse(&self, string: &mut String, indent: usize) {
+        string.push_str(&format!("{}{}\n", " ".repeat(indent*2), self.value));
+        if let Some(left) = &self.left {
+            left.print_traverse(string, indent+1);
+        }
+        if let Some(right) = &self.right {
+            right.print_traverse(string, indent+1);
+        }
+    }
+}
+
+// an immutable ordered set kept balanced as an AVL tree: `insert` and `remove`
+// leave `self` alone and return a new version in O(log n), sharing all but
+// O(log n) nodes with it; cloning a version is O(1)
+pub struct PersistentTree<T> {
+    root: Link<T>,
+    num_elements: usize,
+}
+
+impl<T> PersistentTree<T>
+where
+    T: Ord + Clone
+{
+    pub fn new() -> Self {
+        PersistentTree {
+            root: None,
+            num_elements: 0,
+        }
+    }
+
+    // inserting a value already there gives back a copy of this version
+    pub fn insert(&self, value: T) -> Self {
+        match Node::insert(&self.root, value) {
+            None => self.clone(),
+            Some(root) => PersistentTree {
+                root,
+                num_elements: self.num_elements + 1,
+            },
+        }
+    }
+
+    pub fn remove(&self, value: &T) -> Result<Self, String> {
+        if self.root.is_none() {
+            assert_eq!(self.num_elements, 0);
+            return Err("tree is already empty".to_string());
+        }
+        match Node::remove(&self.root, value) {
+            None => Err("nothing to delete".to_string()),
+            Some(root) => Ok(PersistentTree {
+                root,
+                num_elements: self.num_elements - 1,
+            }),
+        }
+    }
+
+    pub fn contents(&self, value: &T) -> bool {
+        let mut link = &self.root;
+        while let Some(node) = link {
+            match node.value.cmp(value) {
+                Ordering::Less => link = &node.right,
+                Ordering::Equal => return true,
+                Ordering::Greater => link = &node.left,
+            }
+        }
+        false
+    }
+
+    #[allow(dead_code)]
+    pub fn min(&self) -> Option<&T> {
+        let mut node = self.root.as_ref()?;
+        while let Some(left) = &node.left {
+            node = left;
+        }
+        Some(&node.value)
+    }
+
+    #[allow(dead_code)]
+    pub fn max(&self) -> Option<&T> {
+        let mut node = self.root.as_ref()?;
+        while let Some(right) = &node.right {
+            node = right;
+        }
+        Some(&node.value)
+    }
+
+    #[allow(dead_code)]
+    pub fn count(&self) -> usize {
+        self.num_elements
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.num_elements == 0
+    }
+
+    pub fn height(&self) -> usize {
+        Node::height_of(&self.root)
+    }
+
+    // whether both versions are the very same tree, without looking at any value
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (None, None) => true,
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    // checks ordering, recorded heights, AVL balance and the element counter
+    pub fn validate(&self) -> Result<(), TreeViolation<&T>> {
+        let mut actual = 0;
+        if let Some(root) = &self.root {
+            root.validate(None, None, &mut actual)?;
+        }
+        if actual != self.num_elements {
+            return Err(TreeViolation::Count { recorded: self.num_elements, actual });
+        }
+        Ok(())
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        let mut res = Iter {
+            stack: Vec::with_capacity(self.height()),
+        };
+        res.push_left(&self.root);
+        res
+    }
+
+    pub fn infix_traverse(&self) -> Vec<&T> {
+        self.iter().collect()
+    }
+
+    pub fn prefix_traverse(&self) -> Vec<&T> {
+        let mut res = Vec::with_capacity(self.num_elements);
+        if let Some(root) = &self.root {
+            root.prefix_traverse(&mut res);
+        }
+        res
+    }
+
+    #[allow(dead_code)]
+    pub fn postfix_traverse(&self) -> Vec<&T> {
+        let mut res = Vec::with_capacity(self.num_elements);
+        if let Some(root) = &self.root {
+            root.postfix_traverse(&mut res);
+        }
+        res
+    }
+}
+
+// another handle on the same version, sharing every node
+impl<T> Clone for PersistentTree<T> {
+    fn clone(&self) -> Self {
+        PersistentTree {
+            root: self.root.clone(),
+            num_elements: self.num_elements,
+        }
+    }
+}
+
+// nodes whose left subtree has been handed out wait on the stack, the next one on top
+pub struct Iter<'a, T> {
+    stack: Vec<&'a Node<T>>,
+}
+
+impl<'a, T> Iter<'a, T> {
+    fn push_left(&mut self, mut link: &'a Link<T>) {
+        while let Some(node) = link {
+            self.stack.push(node);
+            link = &node.left;
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.stack.pop()?;
+        self.push_left(&node.right);
+        Some(&node.value)
+    }
+}
+
+impl<T> fmt::Display for PersistentTree<T>
+where
+    T: fmt::Display
+{
+    fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.root {
+            None => writeln!(f, "empty tree"),
+            Some(root) => {
+                let mut out = String::new();
+                root.print_traverse(&mut out, 0);
+                write!(f, "{}", &out[..out.len()-1])
+            },
+        }
+    }
+}
+
+// every version a tree went through, numbered from 0 for the empty tree; going
+// back to any of them is an index, so a query on the past costs O(log n) too
+pub struct VersionedTree<T> {
+    versions: Vec<PersistentTree<T>>,
+}
+
+impl<T> VersionedTree<T>
+where
+    T: Ord + Clone
+{
+    pub fn new() -> Self {
+        VersionedTree {
+            versions: vec![PersistentTree::new()],
+        }
+    }
+
+    // returns the number of the version made
+    pub fn insert(&mut self, value: T) -> usize {
+        let next = self.current().insert(value);
+        self.versions.push(next);
+        self.latest()
+    }
+
+    pub fn remove(&mut self, value: &T) -> Result<usize, String> {
+        let next = self.current().remove(value)?;
+        self.versions.push(next);
+        Ok(self.latest())
+    }
+
+    // makes an old version the current one again; the versions in between stay recorded
+    pub fn revert(&mut self, version: usize) -> Result<usize, String> {
+        let old = self.at(version).ok_or(format!("there is no version {}", version))?.clone();
+        self.versions.push(old);
+        Ok(self.latest())
+    }
+
+    pub fn current(&self) -> &PersistentTree<T> {
+        self.versions.last().unwrap()
+    }
+
+    pub fn at(&self, version: usize) -> Option<&PersistentTree<T>> {
+        self.versions.get(version)
+    }
+
+    // number of the current version
+    pub fn latest(&self) -> usize {
+        self.versions.len() - 1
+    }
+
+    pub fn contents_at(&self, version: usize, value: &T) -> Option<bool> {
+        self.at(version).map(|tree| tree.contents(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // updates leave every earlier version untouched
+    #[test]
+    fn versions_stay_intact() {
+        let empty = PersistentTree::new();
+        let small = empty.insert(20).insert(10).insert(30);
+        let large = small.insert(25).insert(40);
+        let removed = large.remove(&20).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(small.infix_traverse(), [&10, &20, &30]);
+        assert_eq!(large.count(), 5);
+        assert_eq!(large.min(), Some(&10));
+        assert_eq!(large.max(), Some(&40));
+        assert_eq!(removed.infix_traverse(), [&10, &25, &30, &40]);
+        assert!(large.contents(&20));
+        for tree in [&empty, &small, &large, &removed] {
+            tree.validate().unwrap();
+        }
+    }
+
+    #[test]
+    fn traversal_orders() {
+        let mut tree = PersistentTree::new();
+        for i in 1..=7 {
+            tree = tree.insert(i);
+        }
+        assert_eq!(tree.prefix_traverse(), [&4, &2, &1, &3, &6, &5, &7]);
+        assert_eq!(tree.postfix_traverse(), [&1, &3, &2, &5, &7, &6, &4]);
+    }
+
+    // values without Display are fine outside printing
+    #[test]
+    fn holds_values_without_display() {
+        let mut history = VersionedTree::new();
+        let first = history.insert(vec![1, 2]);
+        history.insert(vec![3]);
+        assert_eq!(history.contents_at(first, &vec![3]), Some(false));
+        assert_eq!(history.current().count(), 2);
+    }
+}
@@ -0,0 +1,290 @@
+use std::cmp::Ordering;
+use crate::bst::{ Comparator, NaturalOrder };
+
+// which end of the comparator's order comes out first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeapOrder {
+    Min,
+    Max,
+}
+
+// names one pushed value for `change_priority`; it stops working once the
+// value leaves the queue, even if its slot is handed to a later value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    slot: usize,
+    generation: u32,
+}
+
+struct Slot {
+    // index of the value in the heap, or `VACANT`
+    pos: usize,
+    generation: u32,
+}
+
+const VACANT: usize = usize::MAX;
+
+// a binary heap in a Vec: the children of index i sit at 2i + 1 and 2i + 2,
+// and no value comes after either of its children in the queue's order
+pub struct PriorityQueue<T, C = NaturalOrder>
+where
+    C: Comparator<T>
+{
+    // each value with the slot its handle points at
+    heap: Vec<(T, usize)>,
+    slots: Vec<Slot>,
+    // slots of values that left, to be reused
+    free: Vec<usize>,
+    order: HeapOrder,
+    comparator: C,
+}
+
+impl<T> PriorityQueue<T>
+where
+    T: Ord
+{
+    pub fn new(order: HeapOrder) -> Self {
+        Self::with_comparator(order, NaturalOrder)
+    }
+
+    // heapifies `values` in O(n); the values get no handles, so `change_priority`
+    // and `remove` cannot reach them, see `from_vec_with_handles`
+    pub fn from_vec(values: Vec<T>, order: HeapOrder) -> Self {
+        Self::from_vec_with_comparator(values, order, NaturalOrder).0
+    }
+
+    // like `from_vec`, also returning the handle of each value in the order given
+    pub fn from_vec_with_handles(values: Vec<T>, order: HeapOrder) -> (Self, Vec<Handle>) {
+        Self::from_vec_with_comparator(values, order, NaturalOrder)
+    }
+}
+
+impl<T, C> PriorityQueue<T, C>
+where
+    C: Comparator<T>
+{
+    pub fn with_comparator(order: HeapOrder, comparator: C) -> Self {
+        PriorityQueue {
+            heap: Vec::new(),
+            slots: Vec::new(),
+            free: Vec::new(),
+            order,
+            comparator,
+        }
+    }
+
+    // sifts down every parent, from the last one up to the root, which costs
+    // O(n) in total since most of them sit near the bottom; the value at index i
+    // of `values` gets slot i, and its handle comes back at index i
+    pub fn from_vec_with_comparator(values: Vec<T>, order: HeapOrder, comparator: C) -> (Self, Vec<Handle>) {
+        let mut res = Self::with_comparator(order, comparator);
+        res.slots = (0..values.len()).map(|pos| Slot { pos, generation: 0 }).collect();
+        res.heap = values.into_iter().enumerate().map(|(slot, value)| (value, slot)).collect();
+        for pos in (0..res.heap.len() / 2).rev() {
+            res.sift_down(pos);
+        }
+        let handles = (0..res.slots.len()).map(|slot| Handle { slot, generation: 0 }).collect();
+        (res, handles)
+    }
+
+    pub fn push(&mut self, value: T) -> Handle {
+        let pos = self.heap.len();
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.slots[slot].pos = pos;
+                slot
+            },
+            None => {
+                self.slots.push(Slot { pos, generation: 0 });
+                self.slots.len() - 1
+            },
+        };
+        self.heap.push((value, slot));
+        self.sift_up(pos);
+        Handle {
+            slot,
+            generation: self.slots[slot].generation,
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.heap.is_empty() {
+            return None;
+        }
+        let last = self.heap.len() - 1;
+        self.swap(0, last);
+        let (value, slot) = self.heap.pop().unwrap();
+        self.release(slot);
+        if !self.heap.is_empty() {
+            self.sift_down(0);
+        }
+        Some(value)
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.heap.first().map(|(value, _)| value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    #[allow(dead_code)]
+    pub fn order(&self) -> HeapOrder {
+        self.order
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.position(handle).map(|pos| &self.heap[pos].0)
+    }
+
+    // puts `value` in place of the one `handle` names and moves it up or down to
+    // where it now belongs, in O(log n); returns the value it replaced
+    pub fn change_priority(&mut self, handle: Handle, value: T) -> Result<T, String> {
+        let pos = self.position(handle).ok_or("handle does not name a queued value".to_string())?;
+        let old = std::mem::replace(&mut self.heap[pos].0, value);
+        if self.before(&self.heap[pos].0, &old) {
+            self.sift_up(pos);
+        } else {
+            self.sift_down(pos);
+        }
+        Ok(old)
+    }
+
+    // takes the value `handle` names out of the queue, wherever it is
+    #[allow(dead_code)]
+    pub fn remove(&mut self, handle: Handle) -> Result<T, String> {
+        let pos = self.position(handle).ok_or("handle does not name a queued value".to_string())?;
+        let last = self.heap.len() - 1;
+        self.swap(pos, last);
+        let (value, slot) = self.heap.pop().unwrap();
+        self.release(slot);
+        if pos < self.heap.len() {
+            self.sift_up(pos);
+            self.sift_down(pos);
+        }
+        Ok(value)
+    }
+
+    // every value in ascending order of the comparator, whatever the heap order
+    pub fn into_sorted_vec(mut self) -> Vec<T> {
+        let mut res = Vec::with_capacity(self.heap.len());
+        while let Some(value) = self.pop() {
+            res.push(value);
+        }
+        if self.order == HeapOrder::Max {
+            res.reverse();
+        }
+        res
+    }
+
+    // whether `a` leaves the queue ahead of `b`
+    fn before(&self, a: &T, b: &T) -> bool {
+        let ordering = self.comparator.compare(a, b);
+        match self.order {
+            HeapOrder::Min => ordering == Ordering::Less,
+            HeapOrder::Max => ordering == Ordering::Greater,
+        }
+    }
+
+    fn position(&self, handle: Handle) -> Option<usize> {
+        let slot = self.slots.get(handle.slot)?;
+        if slot.generation == handle.generation && slot.pos != VACANT {
+            Some(slot.pos)
+        } else {
+            None
+        }
+    }
+
+    // retires the handle of a value leaving the queue
+    fn release(&mut self, slot: usize) {
+        self.slots[slot].pos = VACANT;
+        self.slots[slot].generation = self.slots[slot].generation.wrapping_add(1);
+        self.free.push(slot);
+    }
+
+    // swaps two heap entries and keeps their slots pointing at them
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        self.slots[self.heap[a].1].pos = a;
+        self.slots[self.heap[b].1].pos = b;
+    }
+
+    fn sift_up(&mut self, mut pos: usize) {
+        while pos > 0 {
+            let parent = (pos - 1) / 2;
+            if !self.before(&self.heap[pos].0, &self.heap[parent].0) {
+                break;
+            }
+            self.swap(pos, parent);
+            pos = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut pos: usize) {
+        loop {
+            let mut first = pos;
+            for child in [2 * pos + 1, 2 * pos + 2] {
+                if child < self.heap.len() && self.before(&self.heap[child].0, &self.heap[first].0) {
+                    first = child;
+                }
+            }
+            if first == pos {
+                break;
+            }
+            self.swap(pos, first);
+            pos = first;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{ Rng, SeedableRng };
+    use rand::rngs::StdRng;
+
+    // values taken out from the middle leave the rest coming out in order
+    #[test]
+    fn remove_by_handle() {
+        let mut rng = StdRng::seed_from_u64(25);
+        let mut queue = PriorityQueue::new(HeapOrder::Max);
+        assert_eq!(queue.order(), HeapOrder::Max);
+        let mut kept = Vec::new();
+        let mut handles = Vec::new();
+        for _ in 0..1000 {
+            let value = rng.gen_range(0..100);
+            handles.push((queue.push(value), value));
+        }
+        for (i, (handle, value)) in handles.iter().enumerate() {
+            if i % 3 == 0 {
+                assert_eq!(queue.remove(*handle), Ok(*value));
+            } else {
+                kept.push(*value);
+            }
+        }
+        kept.sort();
+        assert_eq!(queue.into_sorted_vec(), kept);
+    }
+
+    // a handle stops working once its value leaves, even when its slot is reused
+    #[test]
+    fn stale_handles() {
+        let mut queue = PriorityQueue::new(HeapOrder::Min);
+        let first = queue.push(5);
+        assert_eq!(queue.remove(first), Ok(5));
+        assert!(queue.is_empty());
+        let second = queue.push(7);
+        assert!(queue.remove(first).is_err());
+        assert!(queue.change_priority(first, 1).is_err());
+        assert_eq!(queue.get(second), Some(&7));
+        assert_eq!(queue.pop(), Some(7));
+        assert!(queue.remove(second).is_err());
+        assert!(queue.is_empty());
+    }
+}
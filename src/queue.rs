@@ -1,13 +1,13 @@
-use std::ptr::{ null_mut, addr_of_mut, drop_in_place };
+use std::ptr::{ null_mut, addr_of_mut };
 use std::alloc::{ alloc, dealloc, Layout, handle_alloc_error };
 
-struct Node<T: Clone> {
+struct Node<T> {
     value: T,
     next: *mut Node<T>,
     prev: *mut Node<T>,
 }
 
-impl<T: Clone> Node<T> {
+impl<T> Node<T> {
     fn new_ptr(value: T) -> *mut Self {
         let layout = Layout::new::<Node<T>>();
         unsafe {
@@ -15,17 +15,13 @@ impl<T: Clone> Node<T> {
             if node_ptr.is_null() {
                 handle_alloc_error(layout);
             }
-            (*node_ptr).value = value;
+            addr_of_mut!((*node_ptr).value).write(value);
             (*node_ptr).next = null_mut();
             (*node_ptr).prev = null_mut();
             node_ptr
         }
     }
 
-    fn get_next(&self) -> *mut Node<T> {
-        self.next
-    }
-
     fn set_next(&mut self, node: &mut Node<T>) {
         self.next = node as *mut Node<T>;
     }
@@ -38,27 +34,27 @@ impl<T: Clone> Node<T> {
         self.prev = node as *mut Node<T>;
     }
 
-    fn get_value(&self) -> T {
-        T::clone(&self.value)
+    // frees the old tail once `pop` has moved `tail` off it, returning its value
+    unsafe fn into_value(node_ptr: *mut Self) -> T {
+        let value = std::ptr::read(&(*node_ptr).value);
+        dealloc(node_ptr as *mut u8, Layout::new::<Node<T>>());
+        value
     }
 }
 
-impl<T: Clone> Drop for Node<T> {
-    fn drop(&mut self) {
-        let layout = Layout::new::<Node<T>>();
-        unsafe {
-            dealloc((self as *mut Node<T>) as *mut u8, layout);
-        }
+impl<T: Clone> Node<T> {
+    fn get_value(&self) -> T {
+        T::clone(&self.value)
     }
 }
 
-pub struct Queue<T: Clone> {
+pub struct Queue<T> {
     head: *mut Node<T>,
     tail: *mut Node<T>,
     len: usize,
 }
 
-impl<T: Clone> Queue<T> {
+impl<T> Queue<T> {
     pub fn new() -> Self {
         Queue {
             head: null_mut(),
@@ -87,22 +83,24 @@ impl<T: Clone> Queue<T> {
             None
         } else {
             unsafe {
-                let res = (*self.tail).get_value();
-                let tail_ptr = (*self.tail).get_prev();
-                drop_in_place(self.tail);
-                self.tail = tail_ptr;
+                let tail_ptr = self.tail;
+                self.tail = (*tail_ptr).get_prev();
                 self.len -= 1;
-                Some(res)
+                Some(Node::into_value(tail_ptr))
             }
         }
     }
 
-    pub fn peek(&self) -> Option<T> {
+    #[allow(dead_code)]
+    pub fn peek(&self) -> Option<T>
+    where
+        T: Clone
+    {
         if self.len < 1 {
             None
         } else {
             unsafe {
-                Some((*self.head).get_value())
+                Some((*self.tail).get_value())
             }
         }
     }
@@ -114,23 +112,39 @@ impl<T: Clone> Queue<T> {
     pub fn is_empty(&self) -> bool {
         self.len == 0
     }
+}
 
-    // симметричный обход
-    pub fn infix_traverse(&self) -> Vec<T> {}
+// the nodes belong to the queue alone, so it can move to another thread with its values
+unsafe impl<T: Send> Send for Queue<T> {}
 
-    pub fn prefix_traverse(&self) -> Vec<T> {}
-    pub fn postfix_traverse(&self) -> Vec<T> {}
+impl<T> Drop for Queue<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
 }
 
-impl<T: Clone> Drop for Queue<T> {
-    fn drop(&mut self) {
-        let mut node_ptr = self.head;
-        for _ in 0..self.len {
-            unsafe {
-                let next_ptr = (*node_ptr).get_next();
-                drop_in_place(node_ptr);
-                node_ptr = next_ptr;
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // values leave in the order they came, and peek shows the one pop gives next
+    #[test]
+    fn first_in_first_out() {
+        let mut queue = Queue::new();
+        assert_eq!(queue.peek(), None);
+        for i in 1..=3 {
+            queue.push(i);
+        }
+        assert_eq!(queue.peek(), Some(1));
+        assert_eq!(queue.pop(), Some(1));
+        queue.push(4);
+        assert_eq!(queue.peek(), Some(2));
+        assert_eq!(queue.len(), 3);
+        while let Some(value) = queue.peek() {
+            assert_eq!(queue.pop(), Some(value));
         }
+        assert!(queue.is_empty());
+        queue.push(5);
+        assert_eq!(queue.peek(), Some(5));
     }
 }
@@ -0,0 +1,581 @@
+use std::ptr::{ null_mut, drop_in_place };
+use std::alloc::{ alloc, dealloc, Layout, handle_alloc_error };
+use std::cmp::Ordering;
+use std::fmt;
+use crate::bst::TreeViolation;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Color {
+    Red,
+    Black,
+}
+
+struct Node<T> {
+    value: T,
+    color: Color,
+    parent: *mut Self,
+    left: *mut Self,
+    right: *mut Self,
+}
+
+impl<T> Node<T> {
+    fn new_ptr(value: T) -> *mut Self {
+        let layout = Layout::new::<Node<T>>();
+        unsafe {
+            let node_ptr = alloc(layout) as *mut Node<T>;
+            if node_ptr.is_null() {
+                handle_alloc_error(layout);
+            }
+            node_ptr.write(Node {
+                value,
+                color: Color::Red,
+                parent: null_mut(),
+                left: null_mut(),
+                right: null_mut(),
+            });
+            node_ptr
+        }
+    }
+
+    // drops the whole subtree under `node_ptr` and frees its memory
+    unsafe fn destroy(node_ptr: *mut Self) {
+        drop_in_place(node_ptr);
+        dealloc(node_ptr as *mut u8, Layout::new::<Node<T>>());
+    }
+
+    // frees a single detached node, handing its value back to the caller
+    unsafe fn into_value(node_ptr: *mut Self) -> T {
+        let value = std::ptr::read(&(*node_ptr).value);
+        dealloc(node_ptr as *mut u8, Layout::new::<Node<T>>());
+        value
+    }
+
+    // missing children count as black leaves
+    fn is_red(node: *mut Self) -> bool {
+        !node.is_null() && unsafe { (*node).color == Color::Red }
+    }
+
+    fn go_left_recursively(&self) -> &Self {
+        if self.left.is_null() {
+            self
+        } else {
+            unsafe {
+                (*self.left).go_left_recursively()
+            }
+        }
+    }
+
+    fn go_right_recursively(&self) -> &Self {
+        if self.right.is_null() {
+            self
+        } else {
+            unsafe {
+                (*self.right).go_right_recursively()
+            }
+        }
+    }
+
+    fn infix_traverse<'a>(&'a self, vec: &mut Vec<&'a T>) {
+        if !self.left.is_null() {
+            unsafe {
+                (*self.left).infix_traverse(vec);
+            }
+        }
+        vec.push(&self.value);
+        if !self.right.is_null() {
+            unsafe {
+                (*self.right).infix_traverse(vec);
+            }
+        }
+    }
+
+    fn prefix_traverse<'a>(&'a self, vec: &mut Vec<&'a T>) {
+        vec.push(&self.value);
+        if !self.left.is_null() {
+            unsafe {
+                (*self.left).prefix_traverse(vec);
+            }
+        }
+        if !self.right.is_null() {
+            unsafe {
+                (*self.right).prefix_traverse(vec);
+            }
+        }
+    }
+
+    fn postfix_traverse<'a>(&'a self, vec: &mut Vec<&'a T>) {
+        if !self.left.is_null() {
+            unsafe {
+                (*self.left).postfix_traverse(vec);
+            }
+        }
+        if !self.right.is_null() {
+            unsafe {
+                (*self.right).postfix_traverse(vec);
+            }
+        }
+        vec.push(&self.value);
+    }
+
+}
+
+impl<T> Node<T>
+where
+    T: Ord
+{
+    fn search(&self, value: &T) -> Option<&Self> {
+        unsafe {
+            match self.value.cmp(value) {
+                Ordering::Less => if self.right.is_null() { None } else { (*self.right).search(value) },
+                Ordering::Equal => Some(self),
+                Ordering::Greater => if self.left.is_null() { None } else { (*self.left).search(value) },
+            }
+        }
+    }
+
+    // checks this subtree against the bounds set by its ancestors, returning its black height
+    fn validate<'a>(&'a self, low: Option<&'a T>, high: Option<&'a T>, count: &mut usize) -> Result<usize, TreeViolation<&'a T>> {
+        *count += 1;
+        if let Some(bound) = low.filter(|bound| self.value < **bound).or(high.filter(|bound| self.value > **bound)) {
+            return Err(TreeViolation::Order { node: &self.value, bound });
+        }
+        let mut black_heights = [1, 1];
+        for (i, child) in [self.left, self.right].into_iter().enumerate() {
+            if child.is_null() {
+                continue;
+            }
+            unsafe {
+                let child = &*child;
+                if !std::ptr::eq(child.parent, self) {
+                    return Err(TreeViolation::ParentLink { node: &child.value });
+                }
+                if self.color == Color::Red && child.color == Color::Red {
+                    return Err(TreeViolation::RedChild { node: &child.value });
+                }
+                black_heights[i] = if i == 0 {
+                    child.validate(low, Some(&self.value), count)?
+                } else {
+                    child.validate(Some(&self.value), high, count)?
+                };
+            }
+        }
+        if black_heights[0] != black_heights[1] {
+            return Err(TreeViolation::BlackHeight { node: &self.value });
+        }
+        Ok(black_heights[0] + if self.color == Color::Black { 1 } else { 0 })
+    }
+}
+
+impl<T> Node<T>
+where
+    T: fmt::Display
+{
+    fn print_traverse(&self, string: &mut String, indent: usize) {
+        let color = match self.color {
+            Color::Red => "R",
+            Color::Black => "B",
+        };
+        string.push_str(&format!("{}{} ({})\n", " ".repeat(indent*2), self.value, color));
+        if !self.left.is_null() {
+            unsafe {
+                (*self.left).print_traverse(string, indent+1);
+            }
+        }
+        if !self.right.is_null() {
+            unsafe {
+                (*self.right).print_traverse(string, indent+1);
+            }
+        }
+    }
+}
+
+impl<T> Drop for Node<T> {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.left.is_null() { Node::destroy(self.left); }
+            if !self.right.is_null() { Node::destroy(self.right); }
+        }
+    }
+}
+
+pub struct RedBlackTree<T> {
+    root: *mut Node<T>,
+    num_elements: usize,
+}
+
+impl<T> RedBlackTree<T>
+where
+    T: Ord
+{
+    pub fn new() -> Self {
+        RedBlackTree {
+            root: null_mut(),
+            num_elements: 0,
+        }
+    }
+
+    pub fn insert(&mut self, value: T) {
+        let new_ptr = Node::new_ptr(value);
+        if self.root.is_null() {
+            self.root = new_ptr;
+        } else {
+            unsafe {
+                let mut node_ptr = self.root;
+                loop {
+                    if (*node_ptr).value > (*new_ptr).value {
+                        if (*node_ptr).left.is_null() {
+                            (*node_ptr).left = new_ptr;
+                            break;
+                        }
+                        node_ptr = (*node_ptr).left;
+                    } else {
+                        if (*node_ptr).right.is_null() {
+                            (*node_ptr).right = new_ptr;
+                            break;
+                        }
+                        node_ptr = (*node_ptr).right;
+                    }
+                }
+                (*new_ptr).parent = node_ptr;
+            }
+        }
+        unsafe {
+            self.insert_fixup(new_ptr);
+        }
+        self.num_elements += 1;
+    }
+
+    #[allow(dead_code)]
+    pub fn contents(&self, value: &T) -> bool {
+        if self.root.is_null() {
+            assert_eq!(self.num_elements, 0);
+            false
+        } else {
+            unsafe {
+                (*self.root).search(value).is_some()
+            }
+        }
+    }
+
+    pub fn remove(&mut self, value: &T) -> Result<T, String> {
+        if self.root.is_null() {
+            assert_eq!(self.num_elements, 0);
+            return Err("tree is already empty".to_string());
+        }
+        unsafe {
+            let mut node_ptr = match (*self.root).search(value) {
+                Some(node) => node as *const Node<T> as *mut Node<T>,
+                None => return Err("nothing to delete".to_string()),
+            };
+            if !(*node_ptr).left.is_null() && !(*node_ptr).right.is_null() {
+                let next_ptr = (*(*node_ptr).right).go_left_recursively() as *const Node<T> as *mut Node<T>;
+                std::ptr::swap(&mut (*node_ptr).value, &mut (*next_ptr).value);
+                node_ptr = next_ptr;
+            }
+            let child_ptr = if (*node_ptr).left.is_null() { (*node_ptr).right } else { (*node_ptr).left };
+            let parent_ptr = (*node_ptr).parent;
+            if !child_ptr.is_null() {
+                (*child_ptr).parent = parent_ptr;
+            }
+            self.replace_child(parent_ptr, node_ptr, child_ptr);
+            let removed_color = (*node_ptr).color;
+            let res = Node::into_value(node_ptr);
+            if removed_color == Color::Black {
+                self.remove_fixup(child_ptr, parent_ptr);
+            }
+            self.num_elements -= 1;
+            Ok(res)
+        }
+    }
+
+    pub fn min(&self) -> Option<&T> {
+        if self.root.is_null() {
+            None
+        } else {
+            unsafe {
+                Some( &(*self.root).go_left_recursively().value )
+            }
+        }
+    }
+
+    pub fn max(&self) -> Option<&T> {
+        if self.root.is_null() {
+            assert_eq!(self.num_elements, 0);
+            None
+        } else {
+            unsafe {
+                Some( &(*self.root).go_right_recursively().value )
+            }
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.num_elements
+    }
+
+    // checks ordering, parent links, node colors and the element counter
+    pub fn validate(&self) -> Result<(), TreeViolation<&T>> {
+        let mut actual = 0;
+        if !self.root.is_null() {
+            unsafe {
+                let root = &*self.root;
+                if !root.parent.is_null() {
+                    return Err(TreeViolation::ParentLink { node: &root.value });
+                }
+                if root.color == Color::Red {
+                    return Err(TreeViolation::RedRoot { node: &root.value });
+                }
+                root.validate(None, None, &mut actual)?;
+            }
+        }
+        if actual != self.num_elements {
+            return Err(TreeViolation::Count { recorded: self.num_elements, actual });
+        }
+        Ok(())
+    }
+
+    pub fn infix_traverse(&self) -> Vec<&T> {
+        let mut res = Vec::with_capacity(self.num_elements);
+        if !self.root.is_null() {
+            unsafe {
+                (*self.root).infix_traverse(&mut res);
+            }
+        }
+        res
+    }
+
+    #[allow(dead_code)]
+    pub fn prefix_traverse(&self) -> Vec<&T> {
+        let mut res = Vec::with_capacity(self.num_elements);
+        if !self.root.is_null() {
+            unsafe {
+                (*self.root).prefix_traverse(&mut res);
+            }
+        }
+        res
+    }
+
+    #[allow(dead_code)]
+    pub fn postfix_traverse(&self) -> Vec<&T> {
+        let mut res = Vec::with_capacity(self.num_elements);
+        if !self.root.is_null() {
+            unsafe {
+                (*self.root).postfix_traverse(&mut res);
+            }
+        }
+        res
+    }
+
+    unsafe fn replace_child(&mut self, parent: *mut Node<T>, old: *mut Node<T>, new: *mut Node<T>) {
+        if parent.is_null() {
+            self.root = new;
+        } else if (*parent).left == old {
+            (*parent).left = new;
+        } else {
+            (*parent).right = new;
+        }
+    }
+
+    unsafe fn rotate_left(&mut self, x: *mut Node<T>) {
+        let y = (*x).right;
+        (*x).right = (*y).left;
+        if !(*y).left.is_null() {
+            (*(*y).left).parent = x;
+        }
+        (*y).parent = (*x).parent;
+        self.replace_child((*x).parent, x, y);
+        (*y).left = x;
+        (*x).parent = y;
+    }
+
+    unsafe fn rotate_right(&mut self, x: *mut Node<T>) {
+        let y = (*x).left;
+        (*x).left = (*y).right;
+        if !(*y).right.is_null() {
+            (*(*y).right).parent = x;
+        }
+        (*y).parent = (*x).parent;
+        self.replace_child((*x).parent, x, y);
+        (*y).right = x;
+        (*x).parent = y;
+    }
+
+    // a freshly inserted red node may sit under a red parent; recolor or rotate upwards
+    unsafe fn insert_fixup(&mut self, node: *mut Node<T>) {
+        let mut node_ptr = node;
+        while Node::is_red((*node_ptr).parent) {
+            let parent_ptr = (*node_ptr).parent;
+            let grand_ptr = (*parent_ptr).parent;
+            if parent_ptr == (*grand_ptr).left {
+                let uncle_ptr = (*grand_ptr).right;
+                if Node::is_red(uncle_ptr) {
+                    (*parent_ptr).color = Color::Black;
+                    (*uncle_ptr).color = Color::Black;
+                    (*grand_ptr).color = Color::Red;
+                    node_ptr = grand_ptr;
+                } else {
+                    if node_ptr == (*parent_ptr).right {
+                        node_ptr = parent_ptr;
+                        self.rotate_left(node_ptr);
+                    }
+                    (*(*node_ptr).parent).color = Color::Black;
+                    (*grand_ptr).color = Color::Red;
+                    self.rotate_right(grand_ptr);
+                }
+            } else {
+                let uncle_ptr = (*grand_ptr).left;
+                if Node::is_red(uncle_ptr) {
+                    (*parent_ptr).color = Color::Black;
+                    (*uncle_ptr).color = Color::Black;
+                    (*grand_ptr).color = Color::Red;
+                    node_ptr = grand_ptr;
+                } else {
+                    if node_ptr == (*parent_ptr).left {
+                        node_ptr = parent_ptr;
+                        self.rotate_right(node_ptr);
+                    }
+                    (*(*node_ptr).parent).color = Color::Black;
+                    (*grand_ptr).color = Color::Red;
+                    self.rotate_left(grand_ptr);
+                }
+            }
+        }
+        (*self.root).color = Color::Black;
+    }
+
+    // `node` (possibly null) carries an extra black after a black node was unlinked from `parent`
+    unsafe fn remove_fixup(&mut self, node: *mut Node<T>, parent: *mut Node<T>) {
+        let mut node_ptr = node;
+        let mut parent_ptr = parent;
+        while node_ptr != self.root && !Node::is_red(node_ptr) {
+            if node_ptr == (*parent_ptr).left {
+                let mut sibling_ptr = (*parent_ptr).right;
+                if Node::is_red(sibling_ptr) {
+                    (*sibling_ptr).color = Color::Black;
+                    (*parent_ptr).color = Color::Red;
+                    self.rotate_left(parent_ptr);
+                    sibling_ptr = (*parent_ptr).right;
+                }
+                if !Node::is_red((*sibling_ptr).left) && !Node::is_red((*sibling_ptr).right) {
+                    (*sibling_ptr).color = Color::Red;
+                    node_ptr = parent_ptr;
+                    parent_ptr = (*node_ptr).parent;
+                } else {
+                    if !Node::is_red((*sibling_ptr).right) {
+                        (*(*sibling_ptr).left).color = Color::Black;
+                        (*sibling_ptr).color = Color::Red;
+                        self.rotate_right(sibling_ptr);
+                        sibling_ptr = (*parent_ptr).right;
+                    }
+                    (*sibling_ptr).color = (*parent_ptr).color;
+                    (*parent_ptr).color = Color::Black;
+                    (*(*sibling_ptr).right).color = Color::Black;
+                    self.rotate_left(parent_ptr);
+                    node_ptr = self.root;
+                }
+            } else {
+                let mut sibling_ptr = (*parent_ptr).left;
+                if Node::is_red(sibling_ptr) {
+                    (*sibling_ptr).color = Color::Black;
+                    (*parent_ptr).color = Color::Red;
+                    self.rotate_right(parent_ptr);
+                    sibling_ptr = (*parent_ptr).left;
+                }
+                if !Node::is_red((*sibling_ptr).left) && !Node::is_red((*sibling_ptr).right) {
+                    (*sibling_ptr).color = Color::Red;
+                    node_ptr = parent_ptr;
+                    parent_ptr = (*node_ptr).parent;
+                } else {
+                    if !Node::is_red((*sibling_ptr).left) {
+                        (*(*sibling_ptr).right).color = Color::Black;
+                        (*sibling_ptr).color = Color::Red;
+                        self.rotate_left(sibling_ptr);
+                        sibling_ptr = (*parent_ptr).left;
+                    }
+                    (*sibling_ptr).color = (*parent_ptr).color;
+                    (*parent_ptr).color = Color::Black;
+                    (*(*sibling_ptr).left).color = Color::Black;
+                    self.rotate_right(parent_ptr);
+                    node_ptr = self.root;
+                }
+            }
+        }
+        if !node_ptr.is_null() {
+            (*node_ptr).color = Color::Black;
+        }
+    }
+}
+
+impl<T> Drop for RedBlackTree<T> {
+    fn drop(&mut self) {
+        if !self.root.is_null() {
+            unsafe { Node::destroy(self.root); }
+        }
+    }
+}
+
+impl<T> fmt::Display for RedBlackTree<T>
+where
+    T: fmt::Display
+{
+    fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.num_elements == 0 {
+            writeln!(f, "empty tree")
+        } else {
+            unsafe {
+                let mut out = String::new();
+                (*self.root).print_traverse(&mut out, 0);
+                write!(f, "{}", &out[..out.len()-1])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // owned, non-Copy values go in, and removal hands them back out
+    #[test]
+    fn holds_owned_values() {
+        let mut tree = RedBlackTree::new();
+        for word in ["pear", "fig", "apple", "kiwi", "plum", "date", "lime"] {
+            tree.insert(word.to_string());
+        }
+        assert!(tree.contents(&"fig".to_string()));
+        assert_eq!(tree.remove(&"fig".to_string()), Ok("fig".to_string()));
+        assert!(!tree.contents(&"fig".to_string()));
+        assert!(tree.remove(&"fig".to_string()).is_err());
+        assert_eq!(tree.min().map(String::as_str), Some("apple"));
+        assert_eq!(tree.max().map(String::as_str), Some("plum"));
+        tree.validate().unwrap();
+        assert_eq!(tree.to_string().lines().count(), tree.count());
+    }
+
+    // removals through two-child nodes keep the coloring rules intact
+    #[test]
+    fn keeps_colors_valid() {
+        let mut tree = RedBlackTree::new();
+        for i in 1..=1000 {
+            tree.insert(i);
+        }
+        tree.validate().unwrap();
+        for i in (1..=1000).step_by(3) {
+            assert_eq!(tree.remove(&i), Ok(i));
+            tree.validate().unwrap();
+        }
+        assert_eq!(tree.count(), 666);
+        let expected: Vec<i32> = (1..=1000).filter(|i| i % 3 != 1).collect();
+        assert_eq!(tree.infix_traverse().into_iter().copied().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn traversal_orders() {
+        let mut tree = RedBlackTree::new();
+        for i in [2, 1, 3] {
+            tree.insert(i);
+        }
+        assert_eq!(tree.prefix_traverse(), [&2, &1, &3]);
+        assert_eq!(tree.postfix_traverse(), [&1, &3, &2]);
+    }
+}
@@ -0,0 +1,193 @@
+use std::ptr::{ self, NonNull };
+use std::alloc::{ alloc, dealloc, Layout, handle_alloc_error };
+use std::mem::size_of;
+
+// a FIFO queue over one contiguous buffer used as a ring: the elements occupy
+// `len` slots starting at `head` and wrap around past the end of the buffer
+pub struct RingQueue<T> {
+    buf: *mut T,
+    cap: usize,
+    // slot of the oldest element
+    head: usize,
+    len: usize,
+}
+
+impl<T> RingQueue<T> {
+    pub fn new() -> Self {
+        RingQueue {
+            buf: NonNull::dangling().as_ptr(),
+            // zero-sized values need no memory, so they never make the buffer grow
+            cap: if size_of::<T>() == 0 { usize::MAX } else { 0 },
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut res = Self::new();
+        if capacity > res.cap {
+            res.reallocate(capacity);
+        }
+        res
+    }
+
+    // doubles the buffer when it is full, so a push is O(1) amortized
+    pub fn push(&mut self, value: T) {
+        if self.len == self.cap {
+            self.reallocate(if self.cap == 0 { 4 } else { self.cap.checked_mul(2).expect("capacity overflow") });
+        }
+        unsafe {
+            self.buf.add(self.slot(self.len)).write(value);
+        }
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            None
+        } else {
+            unsafe {
+                let res = self.buf.add(self.head).read();
+                self.head = self.slot(1);
+                self.len -= 1;
+                Some(res)
+            }
+        }
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        if self.len == 0 {
+            None
+        } else {
+            unsafe {
+                Some(&*self.buf.add(self.head))
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    // gives back the slots not in use; the next push grows the buffer again
+    pub fn shrink_to_fit(&mut self) {
+        if size_of::<T>() != 0 && self.cap > self.len {
+            self.reallocate(self.len);
+        }
+    }
+
+    // buffer slot of the element `offset` places behind the oldest one
+    fn slot(&self, offset: usize) -> usize {
+        let res = self.head.wrapping_add(offset);
+        if res >= self.cap || res < self.head { res.wrapping_sub(self.cap) } else { res }
+    }
+
+    // moves the elements to the front of a new buffer of `cap` slots, which must hold them all
+    fn reallocate(&mut self, cap: usize) {
+        let new_buf = if cap == 0 {
+            NonNull::dangling().as_ptr()
+        } else {
+            let layout = Layout::array::<T>(cap).expect("capacity overflow");
+            unsafe {
+                let buf = alloc(layout) as *mut T;
+                if buf.is_null() {
+                    handle_alloc_error(layout);
+                }
+                buf
+            }
+        };
+        unsafe {
+            // the elements run from `head` to the end of the buffer, then on from its start
+            let first = self.len.min(self.cap - self.head);
+            ptr::copy_nonoverlapping(self.buf.add(self.head), new_buf, first);
+            ptr::copy_nonoverlapping(self.buf, new_buf.add(first), self.len - first);
+            if self.cap > 0 {
+                dealloc(self.buf as *mut u8, Layout::array::<T>(self.cap).unwrap());
+            }
+        }
+        self.buf = new_buf;
+        self.cap = cap;
+        self.head = 0;
+    }
+}
+
+impl<T> Drop for RingQueue<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+        if size_of::<T>() != 0 && self.cap > 0 {
+            unsafe {
+                dealloc(self.buf as *mut u8, Layout::array::<T>(self.cap).unwrap());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    // growing and shrinking a buffer whose elements wrap past its end keeps them in order
+    #[test]
+    fn wraps_and_regrows() {
+        let mut ring = RingQueue::with_capacity(4);
+        for i in 0..3 {
+            ring.push(i);
+        }
+        ring.pop();
+        ring.pop();
+        for i in 3..6 {
+            ring.push(i);
+        }
+        assert_eq!(ring.capacity(), 4);
+        ring.push(6);
+        assert_eq!(ring.capacity(), 8);
+        ring.pop();
+        ring.shrink_to_fit();
+        assert_eq!(ring.capacity(), 4);
+        assert_eq!(ring.peek(), Some(&3));
+        let mut rest = Vec::new();
+        while let Some(value) = ring.pop() {
+            rest.push(value);
+        }
+        assert_eq!(rest, [3, 4, 5, 6]);
+        assert!(ring.is_empty());
+        ring.shrink_to_fit();
+        assert_eq!(ring.capacity(), 0);
+        ring.push(7);
+        assert_eq!(ring.pop(), Some(7));
+    }
+
+    #[test]
+    fn zero_sized_values() {
+        let mut ring = RingQueue::new();
+        for _ in 0..1000 {
+            ring.push(());
+        }
+        assert_eq!(ring.len(), 1000);
+        assert_eq!(ring.capacity(), usize::MAX);
+        assert_eq!(ring.pop(), Some(()));
+    }
+
+    #[test]
+    fn drops_leftovers() {
+        let tracker = Rc::new(());
+        let mut ring = RingQueue::new();
+        for _ in 0..10 {
+            ring.push(Rc::clone(&tracker));
+        }
+        ring.pop();
+        assert_eq!(Rc::strong_count(&tracker), 10);
+        drop(ring);
+        assert_eq!(Rc::strong_count(&tracker), 1);
+    }
+}
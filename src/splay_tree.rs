@@ -0,0 +1,557 @@
+use std::ptr::null_mut;
+use std::alloc::{ alloc, dealloc, Layout, handle_alloc_error };
+use std::cmp::{ Ordering, max };
+use std::marker::PhantomData;
+use std::fmt;
+use crate::bst::TreeViolation;
+
+struct Node<T> {
+    value: T,
+    parent: *mut Self,
+    left: *mut Self,
+    right: *mut Self,
+}
+
+impl<T> Node<T> {
+    fn new_ptr(value: T) -> *mut Self {
+        let layout = Layout::new::<Node<T>>();
+        unsafe {
+            let node_ptr = alloc(layout) as *mut Node<T>;
+            if node_ptr.is_null() {
+                handle_alloc_error(layout);
+            }
+            node_ptr.write(Node {
+                value,
+                parent: null_mut(),
+                left: null_mut(),
+                right: null_mut(),
+            });
+            node_ptr
+        }
+    }
+
+    // frees a node already cut out of the tree and returns its value
+    unsafe fn into_value(node_ptr: *mut Self) -> T {
+        let value = std::ptr::read(&(*node_ptr).value);
+        dealloc(node_ptr as *mut u8, Layout::new::<Node<T>>());
+        value
+    }
+
+    fn leftmost(&self) -> &Self {
+        let mut node = self;
+        while !node.left.is_null() {
+            node = unsafe { &*node.left };
+        }
+        node
+    }
+
+    fn rightmost(&self) -> &Self {
+        let mut node = self;
+        while !node.right.is_null() {
+            node = unsafe { &*node.right };
+        }
+        node
+    }
+
+    // next node in order, found without splaying so iterators can take `&self`
+    fn next_node(&self) -> *mut Self {
+        unsafe {
+            if !self.right.is_null() {
+                return (*self.right).leftmost() as *const Self as *mut Self;
+            }
+            let mut node_ptr = self as *const Self as *mut Self;
+            let mut parent_ptr = self.parent;
+            while !parent_ptr.is_null() && (*parent_ptr).right == node_ptr {
+                node_ptr = parent_ptr;
+                parent_ptr = (*parent_ptr).parent;
+            }
+            parent_ptr
+        }
+    }
+
+    // the traversals below walk with an explicit stack, since a splay tree can
+    // be a single path as long as the tree is large
+
+    fn prefix_traverse<'a>(&'a self, vec: &mut Vec<&'a T>) {
+        let mut stack = vec![self as *const Self];
+        while let Some(node_ptr) = stack.pop() {
+            let node = unsafe { &*node_ptr };
+            vec.push(&node.value);
+            for child in [node.right, node.left] {
+                if !child.is_null() {
+                    stack.push(child);
+                }
+            }
+        }
+    }
+
+    // visits node, right, left and reverses the result, which is left, right, node
+    fn postfix_traverse<'a>(&'a self, vec: &mut Vec<&'a T>) {
+        let start = vec.len();
+        let mut stack = vec![self as *const Self];
+        while let Some(node_ptr) = stack.pop() {
+            let node = unsafe { &*node_ptr };
+            vec.push(&node.value);
+            for child in [node.left, node.right] {
+                if !child.is_null() {
+                    stack.push(child);
+                }
+            }
+        }
+        vec[start..].reverse();
+    }
+}
+
+impl<T> Node<T>
+where
+    T: fmt::Display
+{
+    fn print_traverse(&self, string: &mut String, indent: usize) {
+        let mut stack = vec![(self as *const Self, indent)];
+        while let Some((node_ptr, indent)) = stack.pop() {
+            let node = unsafe { &*node_ptr };
+            string.push_str(&format!("{}{}\n", " ".repeat(indent*2), node.value));
+            for child in [node.right, node.left] {
+                if !child.is_null() {
+                    stack.push((child, indent+1));
+                }
+            }
+        }
+    }
+}
+
+// every access splays the node it ends on up to the root, so recently used
+// values stay close to the top and a run of accesses costs O(log n) amortized
+pub struct SplayTree<T>
+where
+    T: Ord
+{
+    root: *mut Node<T>,
+    num_elements: usize,
+    // rotations done so far, to watch the amortized cost of a sequence of accesses
+    rotations: usize,
+}
+
+impl<T> SplayTree<T>
+where
+    T: Ord
+{
+    pub fn new() -> Self {
+        SplayTree {
+            root: null_mut(),
+            num_elements: 0,
+            rotations: 0,
+        }
+    }
+
+    pub fn insert(&mut self, value: T) {
+        let new_ptr = Node::new_ptr(value);
+        if self.root.is_null() {
+            self.root = new_ptr;
+        } else {
+            unsafe {
+                let mut node_ptr = self.root;
+                loop {
+                    if (*node_ptr).value > (*new_ptr).value {
+                        if (*node_ptr).left.is_null() {
+                            (*node_ptr).left = new_ptr;
+                            break;
+                        }
+                        node_ptr = (*node_ptr).left;
+                    } else {
+                        if (*node_ptr).right.is_null() {
+                            (*node_ptr).right = new_ptr;
+                            break;
+                        }
+                        node_ptr = (*node_ptr).right;
+                    }
+                }
+                (*new_ptr).parent = node_ptr;
+                self.splay(new_ptr);
+            }
+        }
+        self.num_elements += 1;
+    }
+
+    // a miss still splays the last node on the search path
+    pub fn contents(&mut self, value: &T) -> bool {
+        let node_ptr = self.find(value);
+        !node_ptr.is_null()
+    }
+
+    pub fn remove(&mut self, value: &T) -> Result<T, String> {
+        if self.root.is_null() {
+            assert_eq!(self.num_elements, 0);
+            return Err("tree is already empty".to_string());
+        }
+        let node_ptr = self.find(value);
+        if node_ptr.is_null() {
+            return Err("nothing to delete".to_string());
+        }
+        unsafe {
+            // `node_ptr` is the root now; its subtrees are joined by splaying
+            // the largest value on the left up and hanging the right side under it
+            let left = (*node_ptr).left;
+            let right = (*node_ptr).right;
+            if left.is_null() {
+                self.root = right;
+                if !right.is_null() {
+                    (*right).parent = null_mut();
+                }
+            } else {
+                (*left).parent = null_mut();
+                self.root = left;
+                let max_ptr = (*left).rightmost() as *const Node<T> as *mut Node<T>;
+                self.splay(max_ptr);
+                (*max_ptr).right = right;
+                if !right.is_null() {
+                    (*right).parent = max_ptr;
+                }
+            }
+            self.num_elements -= 1;
+            Ok(Node::into_value(node_ptr))
+        }
+    }
+
+    // the most recently accessed value
+    pub fn root(&self) -> Option<&T> {
+        if self.root.is_null() {
+            None
+        } else {
+            unsafe { Some(&(*self.root).value) }
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn min(&self) -> Option<&T> {
+        if self.root.is_null() {
+            None
+        } else {
+            unsafe {
+                Some( &(*self.root).leftmost().value )
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn max(&self) -> Option<&T> {
+        if self.root.is_null() {
+            assert_eq!(self.num_elements, 0);
+            None
+        } else {
+            unsafe {
+                Some( &(*self.root).rightmost().value )
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn count(&self) -> usize {
+        self.num_elements
+    }
+
+    #[allow(dead_code)]
+    pub fn rotations(&self) -> usize {
+        self.rotations
+    }
+
+    // number of levels: 0 for an empty tree, 1 for a lone root; walks with an
+    // explicit stack since sorted inserts leave the tree as one long path
+    #[allow(dead_code)]
+    pub fn height(&self) -> usize {
+        let mut res = 0;
+        let mut stack = vec![(self.root, 1)];
+        while let Some((node_ptr, depth)) = stack.pop() {
+            if node_ptr.is_null() {
+                continue;
+            }
+            res = max(res, depth);
+            unsafe {
+                stack.push(((*node_ptr).left, depth + 1));
+                stack.push(((*node_ptr).right, depth + 1));
+            }
+        }
+        res
+    }
+
+    // checks ordering, parent links and the element counter
+    pub fn validate(&self) -> Result<(), TreeViolation<&T>> {
+        let mut actual = 0;
+        unsafe {
+            if !self.root.is_null() && !(*self.root).parent.is_null() {
+                return Err(TreeViolation::ParentLink { node: &(*self.root).value });
+            }
+            // the stack pairs each node with the tightest bounds its ancestors set on it
+            let mut stack: Vec<(*mut Node<T>, Option<&T>, Option<&T>)> = vec![(self.root, None, None)];
+            while let Some((node_ptr, low, high)) = stack.pop() {
+                if node_ptr.is_null() {
+                    continue;
+                }
+                let node = &*node_ptr;
+                actual += 1;
+                if let Some(bound) = low.filter(|bound| node.value < **bound).or(high.filter(|bound| node.value > **bound)) {
+                    return Err(TreeViolation::Order { node: &node.value, bound });
+                }
+                for child in [node.left, node.right] {
+                    if !child.is_null() && (*child).parent != node_ptr {
+                        return Err(TreeViolation::ParentLink { node: &(*child).value });
+                    }
+                }
+                stack.push((node.left, low, Some(&node.value)));
+                stack.push((node.right, Some(&node.value), high));
+            }
+        }
+        if actual != self.num_elements {
+            return Err(TreeViolation::Count { recorded: self.num_elements, actual });
+        }
+        Ok(())
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        let node = if self.root.is_null() {
+            null_mut()
+        } else {
+            unsafe { (*self.root).leftmost() as *const Node<T> as *mut Node<T> }
+        };
+        Iter {
+            node,
+            tree: PhantomData,
+        }
+    }
+
+    pub fn infix_traverse(&self) -> Vec<&T> {
+        self.iter().collect()
+    }
+
+    #[allow(dead_code)]
+    pub fn prefix_traverse(&self) -> Vec<&T> {
+        let mut res = Vec::with_capacity(self.num_elements);
+        if !self.root.is_null() {
+            unsafe {
+                (*self.root).prefix_traverse(&mut res);
+            }
+        }
+        res
+    }
+
+    #[allow(dead_code)]
+    pub fn postfix_traverse(&self) -> Vec<&T> {
+        let mut res = Vec::with_capacity(self.num_elements);
+        if !self.root.is_null() {
+            unsafe {
+                (*self.root).postfix_traverse(&mut res);
+            }
+        }
+        res
+    }
+
+    // searches for `value` and splays the node holding it, or the last node
+    // looked at if there is none; returns the matching node or null
+    fn find(&mut self, value: &T) -> *mut Node<T> {
+        let mut node_ptr = self.root;
+        let mut last_ptr = null_mut();
+        unsafe {
+            while !node_ptr.is_null() {
+                last_ptr = node_ptr;
+                match (*node_ptr).value.cmp(value) {
+                    Ordering::Less => node_ptr = (*node_ptr).right,
+                    Ordering::Equal => break,
+                    Ordering::Greater => node_ptr = (*node_ptr).left,
+                }
+            }
+            if !last_ptr.is_null() {
+                self.splay(last_ptr);
+            }
+        }
+        node_ptr
+    }
+
+    // brings `x` up to the root by zig, zig-zig and zig-zag steps
+    unsafe fn splay(&mut self, x: *mut Node<T>) {
+        while !(*x).parent.is_null() {
+            let parent = (*x).parent;
+            let grandparent = (*parent).parent;
+            let x_is_left = (*parent).left == x;
+            if grandparent.is_null() {
+                self.rotate_up(x);
+            } else if x_is_left == ((*grandparent).left == parent) {
+                self.rotate_up(parent);
+                self.rotate_up(x);
+            } else {
+                self.rotate_up(x);
+                self.rotate_up(x);
+            }
+        }
+    }
+
+    // links `new` into the slot `old` held, as the root or one of `parent`'s children
+    unsafe fn replace_child(&mut self, parent: *mut Node<T>, old: *mut Node<T>, new: *mut Node<T>) {
+        if parent.is_null() {
+            self.root = new;
+        } else if (*parent).left == old {
+            (*parent).left = new;
+        } else {
+            (*parent).right = new;
+        }
+    }
+
+    //       y            x
+    //      / \          / \
+    //     x   c   =>   a   y
+    //    / \              / \
+    //   a   b            b   c
+    // and the mirror image when `x` is a right child
+    unsafe fn rotate_up(&mut self, x: *mut Node<T>) {
+        let y = (*x).parent;
+        if (*y).left == x {
+            (*y).left = (*x).right;
+            if !(*x).right.is_null() {
+                (*(*x).right).parent = y;
+            }
+            (*x).right = y;
+        } else {
+            (*y).right = (*x).left;
+            if !(*x).left.is_null() {
+                (*(*x).left).parent = y;
+            }
+            (*x).left = y;
+        }
+        (*x).parent = (*y).parent;
+        self.replace_child((*y).parent, y, x);
+        (*y).parent = x;
+        self.rotations += 1;
+    }
+}
+
+pub struct Iter<'a, T> {
+    node: *mut Node<T>,
+    tree: PhantomData<&'a Node<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.node.is_null() {
+            return None;
+        }
+        unsafe {
+            let node_ptr = self.node;
+            self.node = (*node_ptr).next_node();
+            Some(&(*node_ptr).value)
+        }
+    }
+}
+
+// frees the nodes one at a time rather than recursively, since the tree can be
+// a single long path; left children are rotated up until the top has none
+impl<T> Drop for SplayTree<T>
+where
+    T: Ord
+{
+    fn drop(&mut self) {
+        let mut node_ptr = self.root;
+        unsafe {
+            while !node_ptr.is_null() {
+                let left = (*node_ptr).left;
+                if left.is_null() {
+                    let right = (*node_ptr).right;
+                    Node::into_value(node_ptr);
+                    node_ptr = right;
+                } else {
+                    (*node_ptr).left = (*left).right;
+                    (*left).right = node_ptr;
+                    node_ptr = left;
+                }
+            }
+        }
+    }
+}
+
+impl<T> fmt::Display for SplayTree<T>
+where
+    T: Ord + fmt::Display
+{
+    fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.num_elements == 0 {
+            writeln!(f, "empty tree")
+        } else {
+            unsafe {
+                let mut out = String::new();
+                (*self.root).print_traverse(&mut out, 0);
+                write!(f, "{}", &out[..out.len()-1])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // sorted inserts leave a single path, yet a whole sorted sweep of lookups
+    // costs O(n) rotations in total, so each access is O(1) amortized
+    #[test]
+    fn amortized_rotations() {
+        let n = 10_000;
+        let mut splay = SplayTree::new();
+        for i in 0..n {
+            splay.insert(i);
+        }
+        assert_eq!(splay.height(), n as usize);
+        let start = splay.rotations();
+        let mut accesses = 0;
+        for _ in 0..3 {
+            for i in 0..n {
+                assert!(splay.contents(&i));
+            }
+            accesses += n;
+        }
+        for _ in 0..1000 {
+            assert!(splay.contents(&4242));
+        }
+        accesses += 1000;
+        // m accesses take at most m (3 log n + 1) rotations, plus the n log n the
+        // starting path can hold in potential
+        let log_n = (n as f64).log2();
+        let bound = accesses as f64 * (3.0 * log_n + 1.0) + n as f64 * log_n;
+        assert!(((splay.rotations() - start) as f64) <= bound);
+        splay.validate().unwrap();
+        assert_eq!(splay.count(), n as usize);
+        assert_eq!(splay.prefix_traverse().len(), n as usize);
+        assert_eq!(splay.postfix_traverse().len(), n as usize);
+        assert_eq!(splay.to_string().lines().count(), n as usize);
+    }
+
+    #[test]
+    fn traversal_orders() {
+        let mut splay = SplayTree::new();
+        for i in [42, 39, 55, 40, 777, 50, 15, 41, 7, 52] {
+            splay.insert(i);
+        }
+        assert_eq!(splay.root(), Some(&52));
+        assert!(splay.contents(&40));
+        assert_eq!(splay.root(), Some(&40));
+        assert_eq!(splay.min(), Some(&7));
+        assert_eq!(splay.max(), Some(&777));
+        let mut prefix = splay.prefix_traverse();
+        assert_eq!(prefix[0], &40);
+        prefix.sort();
+        assert_eq!(prefix, splay.infix_traverse());
+        let mut postfix = splay.postfix_traverse();
+        assert_eq!(postfix.last(), Some(&&40));
+        postfix.sort();
+        assert_eq!(postfix, splay.infix_traverse());
+    }
+
+    #[test]
+    fn holds_owned_values() {
+        let mut splay = SplayTree::new();
+        for word in ["pear", "fig", "apple"] {
+            splay.insert(word.to_string());
+        }
+        assert_eq!(splay.remove(&"fig".to_string()), Ok("fig".to_string()));
+        assert_eq!(splay.count(), 2);
+        splay.validate().unwrap();
+    }
+}
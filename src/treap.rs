@@ -0,0 +1,513 @@
+use std::ptr::{ null_mut, drop_in_place };
+use std::alloc::{ alloc, dealloc, Layout, handle_alloc_error };
+use std::marker::PhantomData;
+use std::fmt;
+use rand::{ Rng, SeedableRng };
+use rand::rngs::StdRng;
+
+struct Node<T> {
+    value: T,
+    // random heap key, no child has a higher one than its parent
+    priority: u64,
+    size: usize,
+    left: *mut Self,
+    right: *mut Self,
+}
+
+impl<T> Node<T> {
+    fn new_ptr(value: T, priority: u64) -> *mut Self {
+        let layout = Layout::new::<Node<T>>();
+        unsafe {
+            let node_ptr = alloc(layout) as *mut Node<T>;
+            if node_ptr.is_null() {
+                handle_alloc_error(layout);
+            }
+            node_ptr.write(Node {
+                value,
+                priority,
+                size: 1,
+                left: null_mut(),
+                right: null_mut(),
+            });
+            node_ptr
+        }
+    }
+
+    // frees `node_ptr`; dropping the node drops its children with it
+    unsafe fn destroy(node_ptr: *mut Self) {
+        drop_in_place(node_ptr);
+        dealloc(node_ptr as *mut u8, Layout::new::<Node<T>>());
+    }
+
+    // frees a node that split and merge have already left on its own, returning its value
+    unsafe fn into_value(node_ptr: *mut Self) -> T {
+        let value = std::ptr::read(&(*node_ptr).value);
+        dealloc(node_ptr as *mut u8, Layout::new::<Node<T>>());
+        value
+    }
+
+    fn size_of(node: *mut Self) -> usize {
+        if node.is_null() {
+            0
+        } else {
+            unsafe { (*node).size }
+        }
+    }
+
+    fn update_size(&mut self) {
+        self.size = 1 + Self::size_of(self.left) + Self::size_of(self.right);
+    }
+
+    // cuts the subtree into the values `goes_left` holds for and the rest; `goes_left`
+    // must hold for a prefix of the order, so only one path is walked
+    unsafe fn split<F>(node_ptr: *mut Self, goes_left: &F) -> (*mut Self, *mut Self)
+    where
+        F: Fn(&T) -> bool
+    {
+        if node_ptr.is_null() {
+            return (null_mut(), null_mut());
+        }
+        let res = if goes_left(&(*node_ptr).value) {
+            let (less, rest) = Self::split((*node_ptr).right, goes_left);
+            (*node_ptr).right = less;
+            (node_ptr, rest)
+        } else {
+            let (less, rest) = Self::split((*node_ptr).left, goes_left);
+            (*node_ptr).left = rest;
+            (less, node_ptr)
+        };
+        (*node_ptr).update_size();
+        res
+    }
+
+    // joins two subtrees where every value of `left` comes before every value of
+    // `right`; the root with the higher priority stays on top
+    unsafe fn merge(left: *mut Self, right: *mut Self) -> *mut Self {
+        if left.is_null() {
+            return right;
+        }
+        if right.is_null() {
+            return left;
+        }
+        if (*left).priority > (*right).priority {
+            (*left).right = Self::merge((*left).right, right);
+            (*left).update_size();
+            left
+        } else {
+            (*right).left = Self::merge(left, (*right).left);
+            (*right).update_size();
+            right
+        }
+    }
+
+    fn go_left_recursively(&self) -> &Self {
+        if self.left.is_null() {
+            self
+        } else {
+            unsafe {
+                (*self.left).go_left_recursively()
+            }
+        }
+    }
+
+    fn go_right_recursively(&self) -> &Self {
+        if self.right.is_null() {
+            self
+        } else {
+            unsafe {
+                (*self.right).go_right_recursively()
+            }
+        }
+    }
+
+    fn height(&self) -> usize {
+        let height_of = |node: *mut Self| if node.is_null() { 0 } else { unsafe { (*node).height() } };
+        1 + height_of(self.left).max(height_of(self.right))
+    }
+
+    fn prefix_traverse<'a>(&'a self, vec: &mut Vec<&'a T>) {
+        vec.push(&self.value);
+        if !self.left.is_null() {
+            unsafe {
+                (*self.left).prefix_traverse(vec);
+            }
+        }
+        if !self.right.is_null() {
+            unsafe {
+                (*self.right).prefix_traverse(vec);
+            }
+        }
+    }
+
+    fn postfix_traverse<'a>(&'a self, vec: &mut Vec<&'a T>) {
+        if !self.left.is_null() {
+            unsafe {
+                (*self.left).postfix_traverse(vec);
+            }
+        }
+        if !self.right.is_null() {
+            unsafe {
+                (*self.right).postfix_traverse(vec);
+            }
+        }
+        vec.push(&self.value);
+    }
+}
+
+impl<T> Node<T>
+where
+    T: fmt::Display
+{
+    fn print_traverse(&self, string: &mut String, indent: usize) {
+        string.push_str(&format!("{}{}\n", " ".repeat(indent*2), self.value));
+        if !self.left.is_null() {
+            unsafe {
+                (*self.left).print_traverse(string, indent+1);
+            }
+        }
+        if !self.right.is_null() {
+            unsafe {
+                (*self.right).print_traverse(string, indent+1);
+            }
+        }
+    }
+}
+
+impl<T> Drop for Node<T> {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.left.is_null() { Node::destroy(self.left); }
+            if !self.right.is_null() { Node::destroy(self.right); }
+        }
+    }
+}
+
+// a binary search tree on the values and a max-heap on random priorities at the
+// same time, which keeps its expected height logarithmic whatever the insertion order
+pub struct Treap<T>
+where
+    T: Ord
+{
+    root: *mut Node<T>,
+    num_elements: usize,
+    rng: StdRng,
+}
+
+impl<T> Treap<T>
+where
+    T: Ord
+{
+    #[allow(dead_code)]
+    pub fn new() -> Self {
+        Self::with_rng(StdRng::from_entropy())
+    }
+
+    // the same seed and the same operations always give the same shape
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_rng(StdRng::seed_from_u64(seed))
+    }
+
+    pub fn with_rng(rng: StdRng) -> Self {
+        Treap {
+            root: null_mut(),
+            num_elements: 0,
+            rng,
+        }
+    }
+
+    pub fn insert(&mut self, value: T) {
+        unsafe {
+            let (less, rest) = Node::split(self.root, &|v: &T| *v < value);
+            let new_ptr = Node::new_ptr(value, self.rng.gen());
+            self.root = Node::merge(Node::merge(less, new_ptr), rest);
+        }
+        self.num_elements += 1;
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        let mut node_ptr = self.root;
+        unsafe {
+            while !node_ptr.is_null() {
+                if (*node_ptr).value < *value {
+                    node_ptr = (*node_ptr).right;
+                } else if (*node_ptr).value > *value {
+                    node_ptr = (*node_ptr).left;
+                } else {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    // removes one value equal to `value`; others equal to it stay in the treap
+    pub fn remove(&mut self, value: &T) -> Result<T, String> {
+        if self.root.is_null() {
+            assert_eq!(self.num_elements, 0);
+            return Err("tree is already empty".to_string());
+        }
+        unsafe {
+            // cut out the values equal to `value`, drop the top one and glue everything back
+            let (less, rest) = Node::split(self.root, &|v: &T| *v < *value);
+            let (equal, greater) = Node::split(rest, &|v: &T| *v <= *value);
+            if equal.is_null() {
+                self.root = Node::merge(less, greater);
+                return Err("nothing to delete".to_string());
+            }
+            let equal_rest = Node::merge((*equal).left, (*equal).right);
+            self.root = Node::merge(Node::merge(less, equal_rest), greater);
+            self.num_elements -= 1;
+            Ok(Node::into_value(equal))
+        }
+    }
+
+    // splits into values < `value` and values >= `value`, in O(log n) expected
+    pub fn split(mut self, value: &T) -> (Self, Self) {
+        let (less, rest) = unsafe { Node::split(self.root, &|v: &T| *v < *value) };
+        let rng = StdRng::seed_from_u64(self.rng.gen());
+        self.root = less;
+        self.num_elements = Node::size_of(less);
+        let other = Treap {
+            root: rest,
+            num_elements: Node::size_of(rest),
+            rng,
+        };
+        (self, other)
+    }
+
+    // joins two treaps in O(log n) expected; every value of `left` must be <=
+    // every value of `right`
+    pub fn merge(mut left: Self, mut right: Self) -> Result<Self, String> {
+        if let (Some(max), Some(min)) = (left.max(), right.min()) {
+            if max > min {
+                return Err("values of the left treap must not exceed the right one".to_string());
+            }
+        }
+        unsafe {
+            left.root = Node::merge(left.root, right.root);
+        }
+        left.num_elements += right.num_elements;
+        right.root = null_mut();
+        right.num_elements = 0;
+        Ok(left)
+    }
+
+    pub fn min(&self) -> Option<&T> {
+        if self.root.is_null() {
+            None
+        } else {
+            unsafe {
+                Some( &(*self.root).go_left_recursively().value )
+            }
+        }
+    }
+
+    pub fn max(&self) -> Option<&T> {
+        if self.root.is_null() {
+            assert_eq!(self.num_elements, 0);
+            None
+        } else {
+            unsafe {
+                Some( &(*self.root).go_right_recursively().value )
+            }
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        debug_assert_eq!(self.num_elements, Node::size_of(self.root));
+        self.num_elements
+    }
+
+    pub fn height(&self) -> usize {
+        if self.root.is_null() {
+            0
+        } else {
+            unsafe { (*self.root).height() }
+        }
+    }
+
+    // checks ordering, the heap order of priorities, subtree sizes and the element counter
+    pub fn validate(&self) -> Result<(), TreapViolation<&T>> {
+        let mut actual = 0;
+        unsafe {
+            // values on the stack carry the range their ancestors allow them
+            let mut stack: Vec<(*mut Node<T>, Option<&T>, Option<&T>)> = vec![(self.root, None, None)];
+            while let Some((node_ptr, low, high)) = stack.pop() {
+                if node_ptr.is_null() {
+                    continue;
+                }
+                let node = &*node_ptr;
+                actual += 1;
+                if let Some(bound) = low.filter(|bound| node.value < **bound).or(high.filter(|bound| node.value > **bound)) {
+                    return Err(TreapViolation::Order { node: &node.value, bound });
+                }
+                for child in [node.left, node.right] {
+                    if !child.is_null() && (*child).priority > node.priority {
+                        return Err(TreapViolation::Priority { node: &(*child).value });
+                    }
+                }
+                let size = 1 + Node::size_of(node.left) + Node::size_of(node.right);
+                if node.size != size {
+                    return Err(TreapViolation::Size { node: &node.value, recorded: node.size, actual: size });
+                }
+                stack.push((node.left, low, Some(&node.value)));
+                stack.push((node.right, Some(&node.value), high));
+            }
+        }
+        if actual != self.num_elements {
+            return Err(TreapViolation::Count { recorded: self.num_elements, actual });
+        }
+        Ok(())
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        let mut iter = Iter {
+            stack: Vec::new(),
+            tree: PhantomData,
+        };
+        iter.push_left(self.root);
+        iter
+    }
+
+    pub fn infix_traverse(&self) -> Vec<&T> {
+        self.iter().collect()
+    }
+
+    pub fn prefix_traverse(&self) -> Vec<&T> {
+        let mut res = Vec::with_capacity(self.num_elements);
+        if !self.root.is_null() {
+            unsafe {
+                (*self.root).prefix_traverse(&mut res);
+            }
+        }
+        res
+    }
+
+    #[allow(dead_code)]
+    pub fn postfix_traverse(&self) -> Vec<&T> {
+        let mut res = Vec::with_capacity(self.num_elements);
+        if !self.root.is_null() {
+            unsafe {
+                (*self.root).postfix_traverse(&mut res);
+            }
+        }
+        res
+    }
+}
+
+// without parent links the way back up is kept on a stack of pending ancestors
+pub struct Iter<'a, T> {
+    stack: Vec<*mut Node<T>>,
+    tree: PhantomData<&'a Node<T>>,
+}
+
+impl<'a, T> Iter<'a, T> {
+    fn push_left(&mut self, node: *mut Node<T>) {
+        let mut node_ptr = node;
+        while !node_ptr.is_null() {
+            self.stack.push(node_ptr);
+            node_ptr = unsafe { (*node_ptr).left };
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node_ptr = self.stack.pop()?;
+        unsafe {
+            self.push_left((*node_ptr).right);
+            Some(&(*node_ptr).value)
+        }
+    }
+}
+
+impl<T> Drop for Treap<T>
+where
+    T: Ord
+{
+    fn drop(&mut self) {
+        if !self.root.is_null() {
+            unsafe { Node::destroy(self.root); }
+        }
+    }
+}
+
+impl<T> fmt::Display for Treap<T>
+where
+    T: Ord + fmt::Display
+{
+    fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.num_elements == 0 {
+            writeln!(f, "empty tree")
+        } else {
+            unsafe {
+                let mut out = String::new();
+                (*self.root).print_traverse(&mut out, 0);
+                write!(f, "{}", &out[..out.len()-1])
+            }
+        }
+    }
+}
+
+// the first broken invariant `Treap::validate` comes across
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreapViolation<T> {
+    // `node` sits in the subtree of `bound` on the side it does not belong
+    Order { node: T, bound: T },
+    // `node` has a higher heap priority than its parent
+    Priority { node: T },
+    Size { node: T, recorded: usize, actual: usize },
+    Count { recorded: usize, actual: usize },
+}
+
+impl<T> fmt::Display for TreapViolation<T>
+where
+    T: fmt::Display
+{
+    fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreapViolation::Order { node, bound } => write!(f, "{} is on the wrong side of {}", node, bound),
+            TreapViolation::Priority { node } => write!(f, "{} outranks its parent in priority", node),
+            TreapViolation::Size { node, recorded, actual } => write!(f, "{} records subtree size {} but has {}", node, recorded, actual),
+            TreapViolation::Count { recorded, actual } => write!(f, "treap records {} elements but holds {}", recorded, actual),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an entropy-seeded treap still keeps order and the heap property on priorities
+    #[test]
+    fn unseeded_stays_valid() {
+        let mut treap = Treap::new();
+        for word in ["pear", "fig", "apple", "kiwi", "plum"] {
+            treap.insert(word.to_string());
+        }
+        treap.validate().unwrap();
+        assert_eq!(treap.remove(&"fig".to_string()), Ok("fig".to_string()));
+        assert_eq!(treap.infix_traverse(), ["apple", "kiwi", "pear", "plum"]);
+    }
+
+    // the same seed always builds the same shape
+    #[test]
+    fn seeded_shape_repeats() {
+        let build = || {
+            let mut treap = Treap::with_seed(42);
+            for i in 1..=15 {
+                treap.insert(i);
+            }
+            treap
+        };
+        let (a, b) = (build(), build());
+        assert_eq!(a.prefix_traverse(), b.prefix_traverse());
+        assert_eq!(a.postfix_traverse(), b.postfix_traverse());
+        let mut postfix = a.postfix_traverse();
+        assert_eq!(postfix.last().copied(), a.prefix_traverse().first().copied());
+        postfix.sort();
+        assert_eq!(postfix, a.infix_traverse());
+    }
+}